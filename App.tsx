@@ -6,7 +6,7 @@ import { AboutPage } from './components/AboutPage';
 
 const App: Component = () => {
   const [route, setRoute] = createSignal<'create' | 'view' | 'about'>('create');
-  const [viewParams, setViewParams] = createSignal<{ id: string; key: string | null } | null>(null);
+  const [viewParams, setViewParams] = createSignal<{ id: string; key: string | null; cap: string | null } | null>(null);
   const [createKey, setCreateKey] = createSignal(0);
   const [forkData, setForkData] = createSignal<{ content: string; language: string } | null>(null);
 
@@ -16,9 +16,12 @@ const App: Component = () => {
       const hash = window.location.hash;
       if (hash.startsWith('#view/')) {
         const content = hash.replace('#view/', '');
-        const [id, keyPart] = content.split('&key=');
-        const key = keyPart ? decodeURIComponent(keyPart) : null;
-        return { route: 'view' as const, params: { id, key } };
+        const [id, ...fragmentParams] = content.split('&');
+        const param = (name: string) => {
+          const part = fragmentParams.find((p) => p.startsWith(`${name}=`));
+          return part ? decodeURIComponent(part.slice(name.length + 1)) : null;
+        };
+        return { route: 'view' as const, params: { id, key: param('key'), cap: param('cap') } };
       } else if (hash === '#about') {
         return { route: 'about' as const, params: null };
       }
@@ -102,6 +105,7 @@ const App: Component = () => {
             <ViewPaste
               pasteId={viewParams()!.id}
               decryptionKey={viewParams()!.key}
+              capability={viewParams()!.cap}
               onBack={() => handleNavigate('create')}
               onFork={handleFork}
             />
@@ -0,0 +1,394 @@
+use crate::audit::AuditEntry;
+use crate::client::ClientIdentity;
+use crate::error::AppError;
+use crate::handlers::AppState;
+use crate::model::{Block, Report};
+use crate::tenant::{Tenant, Tenants};
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
+};
+use constant_time_eq::constant_time_eq;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::signal::unix::{signal, SignalKind};
+
+const MAX_REASON_LEN: usize = 500;
+/// Reports returned by one listing; dismissing them makes room for more.
+const MAX_LISTED_REPORTS: usize = 1000;
+
+/// What a wipe destroyed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct WipeReport {
+    pub pastes: u64,
+    pub pow_salts: u64,
+    pub reports: u64,
+}
+
+/// A paste as moderators see it: everything but the ciphertext and the
+/// secrets needed to open or burn it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasteInspection {
+    pub id: String,
+    pub paste: Option<PasteSummary>,
+    pub block: Option<Block>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasteSummary {
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub burn_after_read: bool,
+    pub has_password: bool,
+    pub views: i64,
+    pub size_bytes: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockRequest {
+    pub reason: Option<String>,
+    /// Defaults to the tenant's longest paste TTL.
+    pub ttl_secs: Option<u64>,
+}
+
+/// Which tenant an admin request is about; the instance-wide one by default.
+#[derive(Debug, Deserialize)]
+pub struct Scope {
+    #[serde(default)]
+    pub tenant: String,
+}
+
+/// Operator endpoints. With `ADMIN_TOKEN` set every request needs
+/// `Authorization: Bearer <token>`; without it the routes are only served on
+/// the private `ADMIN_ADDR` listener.
+pub fn router(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/admin/wipe", post(wipe_handler))
+        .route(
+            "/api/v1/admin/paste/:id",
+            get(inspect_paste).delete(delete_paste),
+        )
+        .route(
+            "/api/v1/admin/blocklist/:id",
+            put(block_paste).delete(unblock_paste),
+        )
+        .route("/api/v1/admin/reports", get(list_reports))
+        .route("/api/v1/admin/reports/:id", delete(dismiss_reports))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .layer(Extension(Tenants::new(state)))
+}
+
+async fn require_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(expected) = state.config.admin.token.as_deref() else {
+        return Ok(next.run(request).await);
+    };
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AppError::AdminUnauthorized)?;
+    if !constant_time_eq(presented.as_bytes(), expected.as_bytes()) {
+        return Err(AppError::AdminUnauthorized);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Full address, unlike the /64 used for rate limiting.
+fn actor(identity: Option<Extension<ClientIdentity>>) -> String {
+    match identity.map(|Extension(identity)| identity) {
+        Some(ClientIdentity::Ip(ip)) => format!("ip:{}", ip),
+        Some(ClientIdentity::TorCircuit(circuit)) => format!("circuit:{}", circuit),
+        Some(ClientIdentity::Local) => "local".to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+fn tenant<'a>(tenants: &'a Tenants, scope: &Scope) -> Result<&'a Arc<Tenant>, AppError> {
+    tenants
+        .by_name(&scope.tenant)
+        .ok_or_else(|| AppError::InvalidPayload(format!("unknown tenant {:?}", scope.tenant)))
+}
+
+fn audit<T>(
+    state: &AppState,
+    action: &'static str,
+    tenant: &Tenant,
+    id: &str,
+    actor: &str,
+    detail: Option<&str>,
+    result: &Result<T, AppError>,
+) {
+    state.audit.record(&AuditEntry {
+        action,
+        tenant: &tenant.name,
+        id: Some(id),
+        actor,
+        outcome: result.as_ref().map_or_else(AppError::code, |_| "ok"),
+        detail,
+    });
+}
+
+async fn inspect_paste(
+    State(state): State<AppState>,
+    Extension(tenants): Extension<Tenants>,
+    identity: Option<Extension<ClientIdentity>>,
+    Path(id): Path<String>,
+    Query(scope): Query<Scope>,
+) -> Result<Json<PasteInspection>, AppError> {
+    let tenant = tenant(&tenants, &scope)?;
+    let result = tenant.repository.inspect(&id).await;
+    audit(
+        &state,
+        "inspect",
+        tenant,
+        &id,
+        &actor(identity),
+        None,
+        &result,
+    );
+    let (paste, block) = result?;
+    Ok(Json(PasteInspection {
+        id,
+        paste: paste.map(|paste| PasteSummary {
+            created_at: paste.created_at,
+            expires_at: paste.expires_at,
+            burn_after_read: paste.burn_after_read,
+            has_password: paste.has_password,
+            views: paste.views,
+            size_bytes: paste.data.len(),
+        }),
+        block,
+    }))
+}
+
+async fn delete_paste(
+    State(state): State<AppState>,
+    Extension(tenants): Extension<Tenants>,
+    identity: Option<Extension<ClientIdentity>>,
+    Path(id): Path<String>,
+    Query(scope): Query<Scope>,
+) -> Result<StatusCode, AppError> {
+    let tenant = tenant(&tenants, &scope)?;
+    let result = async {
+        match tenant.repository.inspect(&id).await? {
+            (Some(_), _) => tenant.repository.delete_paste(&id).await,
+            (None, _) => Err(AppError::PasteNotFound),
+        }
+    }
+    .await;
+    audit(
+        &state,
+        "delete",
+        tenant,
+        &id,
+        &actor(identity),
+        None,
+        &result,
+    );
+    result.map(|()| StatusCode::NO_CONTENT)
+}
+
+async fn block_paste(
+    State(state): State<AppState>,
+    Extension(tenants): Extension<Tenants>,
+    identity: Option<Extension<ClientIdentity>>,
+    Path(id): Path<String>,
+    Query(scope): Query<Scope>,
+    payload: Result<Json<BlockRequest>, JsonRejection>,
+) -> Result<StatusCode, AppError> {
+    let tenant = tenant(&tenants, &scope)?;
+    let Json(request) = payload?;
+    if request
+        .reason
+        .as_ref()
+        .is_some_and(|reason| reason.len() > MAX_REASON_LEN)
+    {
+        return Err(AppError::InvalidPayload(format!(
+            "reason must be at most {} bytes",
+            MAX_REASON_LEN
+        )));
+    }
+    let ttl_seconds = request
+        .ttl_secs
+        .unwrap_or(tenant.paste_ttl.max.as_secs())
+        .max(1);
+    let block = Block {
+        reason: request.reason,
+        blocked_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64,
+    };
+    let result = tenant.repository.block(&id, &block, ttl_seconds).await;
+    let detail = format!("ttl={}s reason={:?}", ttl_seconds, block.reason);
+    audit(
+        &state,
+        "block",
+        tenant,
+        &id,
+        &actor(identity),
+        Some(&detail),
+        &result,
+    );
+    result.map(|()| StatusCode::NO_CONTENT)
+}
+
+async fn unblock_paste(
+    State(state): State<AppState>,
+    Extension(tenants): Extension<Tenants>,
+    identity: Option<Extension<ClientIdentity>>,
+    Path(id): Path<String>,
+    Query(scope): Query<Scope>,
+) -> Result<StatusCode, AppError> {
+    let tenant = tenant(&tenants, &scope)?;
+    let result = match tenant.repository.unblock(&id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AppError::PasteNotFound),
+        Err(err) => Err(err),
+    };
+    audit(
+        &state,
+        "unblock",
+        tenant,
+        &id,
+        &actor(identity),
+        None,
+        &result,
+    );
+    result.map(|()| StatusCode::NO_CONTENT)
+}
+
+async fn list_reports(
+    State(state): State<AppState>,
+    Extension(tenants): Extension<Tenants>,
+    identity: Option<Extension<ClientIdentity>>,
+    Query(scope): Query<Scope>,
+) -> Result<Json<Vec<Report>>, AppError> {
+    let tenant = tenant(&tenants, &scope)?;
+    let result = tenant.repository.reports(MAX_LISTED_REPORTS).await;
+    state.audit.record(&AuditEntry {
+        action: "list_reports",
+        tenant: &tenant.name,
+        id: None,
+        actor: &actor(identity),
+        outcome: result.as_ref().map_or_else(AppError::code, |_| "ok"),
+        detail: None,
+    });
+    result.map(Json)
+}
+
+async fn dismiss_reports(
+    State(state): State<AppState>,
+    Extension(tenants): Extension<Tenants>,
+    identity: Option<Extension<ClientIdentity>>,
+    Path(id): Path<String>,
+    Query(scope): Query<Scope>,
+) -> Result<StatusCode, AppError> {
+    let tenant = tenant(&tenants, &scope)?;
+    let result = match tenant.repository.dismiss_reports(&id).await {
+        Ok(0) => Err(AppError::PasteNotFound),
+        Ok(_) => Ok(()),
+        Err(err) => Err(err),
+    };
+    audit(
+        &state,
+        "dismiss_reports",
+        tenant,
+        &id,
+        &actor(identity),
+        None,
+        &result,
+    );
+    result.map(|()| StatusCode::NO_CONTENT)
+}
+
+async fn wipe_handler(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+) -> Result<Json<WipeReport>, AppError> {
+    Ok(Json(wipe(&state, &actor(identity)).await?))
+}
+
+/// Destroys every paste, PoW salt and report, of the instance and of every
+/// tenant, and rotates the PoW secret.
+///
+/// Salts are what stops a solved challenge from being replayed, so the
+/// secret is rotated both before the wipe (invalidating every challenge
+/// handed out so far) and after it (invalidating any handed out meanwhile).
+pub async fn wipe(state: &AppState, actor: &str) -> Result<WipeReport, AppError> {
+    state.hmac_secret.rotate();
+    let result = wipe_stores(state, actor, true).await;
+    state.hmac_secret.rotate();
+    result
+}
+
+/// `wipe` for a process other than the running servers (`ghostbin-server
+/// wipe`). It cannot rotate their PoW secret, so it keeps the salts: without
+/// them, solutions already spent against those servers would work again
+/// until their challenges expire.
+pub async fn wipe_external(state: &AppState, actor: &str) -> Result<WipeReport, AppError> {
+    wipe_stores(state, actor, false).await
+}
+
+async fn wipe_stores(
+    state: &AppState,
+    actor: &str,
+    pow_salts: bool,
+) -> Result<WipeReport, AppError> {
+    let mut report = WipeReport::default();
+    let mut result = Ok(());
+    for tenant in Tenants::new(state).all() {
+        match tenant.repository.wipe(pow_salts).await {
+            Ok(wiped) => {
+                report.pastes += wiped.pastes;
+                report.pow_salts += wiped.pow_salts;
+                report.reports += wiped.reports;
+            }
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        }
+    }
+
+    let detail = format!(
+        "pastes={} pow_salts={} reports={}",
+        report.pastes, report.pow_salts, report.reports
+    );
+    state.audit.record(&AuditEntry {
+        action: "wipe",
+        tenant: "",
+        id: None,
+        actor,
+        outcome: result.as_ref().map_or_else(AppError::code, |_| "ok"),
+        detail: Some(&detail),
+    });
+    result.map(|()| report)
+}
+
+/// Runs `wipe` whenever the process receives `kind`.
+pub fn wipe_on_signal(state: AppState, kind: SignalKind) -> io::Result<()> {
+    let mut signals = signal(kind)?;
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            if let Err(err) = wipe(&state, "signal").await {
+                tracing::error!(error = ?err, "wipe on signal failed");
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use crate::config::RedisConfig;
+use crate::error::AppError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Longest a tripped breaker waits before probing Redis again.
+const MAX_OPEN_FOR: Duration = Duration::from_secs(30);
+
+/// Fast-fails Redis calls while Redis is known to be down, so a blip turns
+/// into a short burst of 503s with `Retry-After` instead of every request
+/// waiting out its own timeout.
+///
+/// After `failure_threshold` consecutive failures the breaker opens for
+/// `open_for`. The first call after that is let through as a probe: success
+/// closes the breaker, failure re-opens it for twice as long (up to 30s).
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Mutex<Inner>>,
+    failure_threshold: u32,
+    base_open_for: Duration,
+}
+
+struct Inner {
+    state: State,
+    open_for: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe is in flight. If it never reports back (e.g. the request was
+    /// dropped), another probe is allowed once `open_for` has passed.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &RedisConfig) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                state: State::Closed { failures: 0 },
+                open_for: config.breaker_open_for,
+            })),
+            failure_threshold: config.breaker_failure_threshold.max(1),
+            base_open_for: config.breaker_open_for,
+        }
+    }
+
+    /// Whether a call may go to Redis right now.
+    pub fn check(&self) -> Result<(), AppError> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        match inner.state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(unavailable(until - now)),
+            State::HalfOpen { since } if now < since + inner.open_for => {
+                Err(unavailable(since + inner.open_for - now))
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                inner.state = State::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if !matches!(inner.state, State::Closed { .. }) {
+            tracing::info!("Redis reachable again, closing circuit breaker");
+            inner.open_for = self.base_open_for;
+        }
+        inner.state = State::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        match inner.state {
+            State::Closed { failures } if failures + 1 < self.failure_threshold => {
+                inner.state = State::Closed {
+                    failures: failures + 1,
+                };
+            }
+            State::Closed { .. } => {
+                tracing::warn!(open_for = ?inner.open_for, "Redis unavailable, opening circuit breaker");
+                inner.state = State::Open {
+                    until: now + inner.open_for,
+                };
+            }
+            State::HalfOpen { .. } => {
+                inner.open_for = (inner.open_for * 2).min(MAX_OPEN_FOR);
+                inner.state = State::Open {
+                    until: now + inner.open_for,
+                };
+            }
+            // Calls that started before the breaker opened.
+            State::Open { .. } => {}
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(self.inner.lock().unwrap().state, State::Closed { .. })
+    }
+
+    /// Runs `call` under the breaker, counting connection failures and
+    /// timeouts against it. Other errors (a missing paste, a bad payload)
+    /// mean Redis answered and count as success.
+    pub async fn call<T>(
+        &self,
+        call: impl std::future::Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        self.check()?;
+        let result = call.await;
+        match &result {
+            Err(err) if err.is_storage_outage() => self.record_failure(),
+            _ => self.record_success(),
+        }
+        result
+    }
+}
+
+fn unavailable(wait: Duration) -> AppError {
+    AppError::StorageUnavailable {
+        retry_after_secs: wait.as_millis().div_ceil(1000).max(1) as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(threshold: u32, open_for: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(&RedisConfig {
+            breaker_failure_threshold: threshold,
+            breaker_open_for: open_for,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_opens_after_threshold_and_sets_retry_after() {
+        let breaker = breaker(2, Duration::from_secs(5));
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+        breaker.record_failure();
+
+        match breaker.check() {
+            Err(AppError::StorageUnavailable { retry_after_secs }) => {
+                assert!((1..=5).contains(&retry_after_secs))
+            }
+            other => panic!("expected open breaker, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = breaker(1, Duration::from_millis(10));
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(15));
+
+        // One probe goes through, concurrent calls keep failing fast.
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_err());
+
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(15));
+        // Backed off to 20ms.
+        assert!(breaker.check().is_err());
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(breaker.check().is_ok());
+
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.check().is_ok());
+    }
+}
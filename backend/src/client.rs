@@ -0,0 +1,146 @@
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Tor's `HiddenServiceExportCircuitID haproxy` encodes the global circuit ID
+/// in the low 32 bits of a source address under this /64.
+const TOR_CIRCUIT_PREFIX: [u16; 4] = [0xfc00, 0xdead, 0xbeef, 0x4dad];
+
+/// Who is on the other end of a connection, as far as throttling is concerned.
+/// Inserted as a request extension by the listener.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientIdentity {
+    TorCircuit(u32),
+    Ip(IpAddr),
+    /// Unix socket peer without a PROXY header.
+    Local,
+}
+
+impl ClientIdentity {
+    pub fn from_ip(ip: IpAddr) -> Self {
+        if let IpAddr::V6(v6) = ip {
+            let segments = v6.segments();
+            if segments[..4] == TOR_CIRCUIT_PREFIX {
+                let circuit = (u32::from(segments[6]) << 16) | u32::from(segments[7]);
+                return ClientIdentity::TorCircuit(circuit);
+            }
+        }
+        ClientIdentity::Ip(ip.to_canonical())
+    }
+
+    /// The client a trusted reverse proxy forwarded the request for. Only
+    /// applies when the peer is in `trusted_proxies`: `X-Forwarded-For` is
+    /// read from the right, past any hops that are trusted proxies too, with
+    /// `X-Real-IP` as the fallback. Anyone else's headers are ignored.
+    pub fn forwarded(self, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> Self {
+        let ClientIdentity::Ip(peer) = self else {
+            return self;
+        };
+        let trusted = |ip: IpAddr| {
+            trusted_proxies
+                .iter()
+                .any(|net| net.contains(&ip.to_canonical()))
+        };
+        if !trusted(peer) {
+            return self;
+        }
+
+        let hops: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        let mut client = peer;
+        if hops.is_empty() {
+            if let Some(ip) = headers
+                .get("x-real-ip")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+            {
+                client = ip;
+            }
+        }
+        for hop in hops.iter().rev() {
+            if !trusted(client) {
+                break;
+            }
+            match hop.parse() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+        }
+        ClientIdentity::from_ip(client)
+    }
+
+    /// Key for per-client buckets. IPv6 clients are grouped by /64 since a
+    /// single host usually controls the whole prefix.
+    pub fn rate_limit_key(&self) -> String {
+        match self {
+            ClientIdentity::TorCircuit(circuit) => format!("circuit:{}", circuit),
+            ClientIdentity::Local => "local".to_string(),
+            ClientIdentity::Ip(IpAddr::V4(ip)) => format!("ip:{}", ip),
+            ClientIdentity::Ip(IpAddr::V6(ip)) => {
+                let s = ip.segments();
+                let prefix = Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0);
+                format!("ip:{}/64", prefix)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tor_circuit_id() {
+        let ip: IpAddr = "fc00:dead:beef:4dad::1:29".parse().unwrap();
+        assert_eq!(
+            ClientIdentity::from_ip(ip),
+            ClientIdentity::TorCircuit(0x10029)
+        );
+        assert_eq!(
+            ClientIdentity::from_ip(ip).rate_limit_key(),
+            "circuit:65577"
+        );
+    }
+
+    #[test]
+    fn test_ip_keys() {
+        let v4 = ClientIdentity::from_ip("::ffff:203.0.113.7".parse().unwrap());
+        assert_eq!(v4.rate_limit_key(), "ip:203.0.113.7");
+
+        let v6 = ClientIdentity::from_ip("2001:db8:1:2:3:4:5:6".parse().unwrap());
+        assert_eq!(v6.rate_limit_key(), "ip:2001:db8:1:2::/64");
+    }
+
+    #[test]
+    fn test_forwarded_only_from_trusted_proxies() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let proxy = ClientIdentity::from_ip("::ffff:10.0.0.2".parse().unwrap());
+        let stranger = ClientIdentity::from_ip("198.51.100.1".parse().unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "192.0.2.66, 203.0.113.7, 10.0.0.3".parse().unwrap(),
+        );
+
+        // The spoofable left end is never reached: 203.0.113.7 is the first
+        // hop no trusted proxy vouches for beyond.
+        assert_eq!(
+            proxy.forwarded(&headers, &trusted).rate_limit_key(),
+            "ip:203.0.113.7"
+        );
+        assert_eq!(stranger.forwarded(&headers, &trusted), stranger);
+        assert_eq!(proxy.forwarded(&headers, &[]), proxy);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "fc00:dead:beef:4dad::1:29".parse().unwrap());
+        assert_eq!(
+            proxy.forwarded(&headers, &trusted),
+            ClientIdentity::TorCircuit(0x10029)
+        );
+    }
+}
@@ -0,0 +1,1169 @@
+use crate::cors::OriginPattern;
+use crate::frontend::FrontendSource;
+use crate::listener::{ListenerConfig, ListenerSpec};
+use crate::logging::LogFormat;
+use crate::rate_limit::{LimitedRoute, RateLimitPolicy};
+use crate::security_headers;
+use axum::http::{HeaderName, HeaderValue};
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::signal::unix::SignalKind;
+
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub log_format: LogFormat,
+    pub server: ServerConfig,
+    pub rate_limit: RateLimitConfig,
+    /// Serve the SPA from the backend as well, replacing the nginx container.
+    pub frontend: Option<FrontendSource>,
+    pub security_headers: SecurityHeadersConfig,
+    pub cors: CorsConfig,
+    pub timeouts: TimeoutConfig,
+    pub memory: MemoryConfig,
+    pub store: StoreConfig,
+    pub encryption: EncryptionConfig,
+    pub admin: AdminConfig,
+    pub reports: ReportConfig,
+    pub access: AccessConfig,
+    pub burn: BurnConfig,
+    pub hardening: HardeningConfig,
+    pub redis: RedisConfig,
+    pub paste_ttl: TtlPolicy,
+    /// Branded hostnames with their own limits, TTLs, CORS origins and key
+    /// namespace. Requests for any other host use the settings above.
+    pub tenants: Vec<TenantConfig>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub listeners: Vec<ListenerConfig>,
+    pub tls: Option<TlsConfig>,
+    /// Separate plain-HTTP listener for `/metrics`.
+    pub metrics_addr: Option<SocketAddr>,
+    /// Reverse proxies trusted to name the client in `X-Forwarded-For` or
+    /// `X-Real-IP`, so rate limits apply per client rather than per proxy.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listeners: vec![ListenerConfig {
+                spec: ListenerSpec::Tcp(SocketAddr::from(([0, 0, 0, 0], 8080))),
+                proxy_protocol: false,
+                plain: false,
+            }],
+            tls: None,
+            metrics_addr: None,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// Optional plain-HTTP listener that redirects everything to HTTPS.
+    pub redirect_from: Option<SocketAddr>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// Off by default: buckets are per client, which takes a real client
+    /// identity (direct connections, the PROXY protocol or `TRUSTED_PROXIES`).
+    /// Behind a proxy that provides none, every user would share one bucket.
+    /// Identical abuse reports are throttled either way.
+    pub enabled: bool,
+    pub policies: HashMap<LimitedRoute, RateLimitPolicy>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            policies: LimitedRoute::ALL
+                .iter()
+                .map(|route| (*route, route.default_policy()))
+                .collect(),
+        }
+    }
+}
+
+/// Hardening headers set on every response, API and SPA alike.
+#[derive(Clone, Debug)]
+pub struct SecurityHeadersConfig {
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            headers: security_headers::defaults(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<OriginPattern>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![OriginPattern::Exact("http://localhost:3000".into())],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TimeoutConfig {
+    /// Request line and headers; the connection is closed when it runs out.
+    pub header_read: Duration,
+    /// The full request body, answered with 408 when it runs out.
+    pub body_read: Duration,
+    /// Everything from the first body byte to the response head (503).
+    pub request: Duration,
+    /// Each Redis call, including the wait for a pooled connection (503).
+    pub redis_command: Duration,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            header_read: Duration::from_secs(10),
+            body_read: Duration::from_secs(30),
+            request: Duration::from_secs(60),
+            redis_command: crate::repository::DEFAULT_COMMAND_TIMEOUT,
+        }
+    }
+}
+
+/// Lifetime of a paste. `default` applies when the client asks for none (or
+/// for "never"); longer requests are cut down to `max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TtlPolicy {
+    pub default: Duration,
+    pub max: Duration,
+}
+
+impl Default for TtlPolicy {
+    fn default() -> Self {
+        Self {
+            default: Duration::from_secs(30 * 24 * 60 * 60),
+            max: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TenantConfig {
+    /// Lowercase `[a-z0-9-]`; also the tenant's key namespace (`<name>:`).
+    pub name: String,
+    /// Lowercase host names without port.
+    pub hosts: Vec<String>,
+    pub rate_limit: RateLimitConfig,
+    pub paste_ttl: TtlPolicy,
+    pub cors: CorsConfig,
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryConfig {
+    /// Fraction of the limit past which new pastes are refused.
+    pub high_water_ratio: f64,
+    /// Used instead of Redis' `maxmemory` when set.
+    pub limit_bytes: Option<u64>,
+    pub poll_interval: Duration,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            high_water_ratio: 0.85,
+            limit_bytes: None,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Where pastes, PoW salts and rate-limit buckets are kept.
+#[derive(Clone, Debug, Default)]
+pub enum StoreConfig {
+    #[default]
+    Redis,
+    /// In-process map instead of Redis, for single-binary deployments.
+    #[cfg(feature = "embedded-store")]
+    Embedded(EmbeddedStoreConfig),
+}
+
+#[derive(Clone, Debug)]
+pub struct EmbeddedStoreConfig {
+    /// Upper bound for stored keys and values; writes past it get 507.
+    pub budget_bytes: u64,
+    /// Independently locked partitions of the map.
+    pub shards: usize,
+    /// Pin the process in RAM so pastes never reach swap.
+    pub mlock: bool,
+}
+
+impl Default for EmbeddedStoreConfig {
+    fn default() -> Self {
+        Self {
+            budget_bytes: 256 * 1024 * 1024,
+            shards: 16,
+            mlock: false,
+        }
+    }
+}
+
+/// Operator access; see `admin`.
+#[derive(Clone, Default)]
+pub struct AdminConfig {
+    /// Bearer token for `/api/v1/admin/*`. On the public listeners the routes
+    /// do not exist without it.
+    pub token: Option<String>,
+    /// Serve the admin routes only on this separate plain-HTTP listener.
+    pub bind: Option<SocketAddr>,
+    /// JSON lines of every admin action. Required with the admin routes;
+    /// signal wipes alone fall back to the `audit` log target.
+    pub audit_log_path: Option<PathBuf>,
+    /// Signal that wipes the store, like `POST /api/v1/admin/wipe`.
+    pub wipe_signal: Option<SignalKind>,
+}
+
+impl fmt::Debug for AdminConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminConfig")
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("bind", &self.bind)
+            .field("audit_log_path", &self.audit_log_path)
+            .field("wipe_signal", &self.wipe_signal)
+            .finish()
+    }
+}
+
+/// Public abuse reports; see `handlers::report_paste`.
+#[derive(Clone, Debug)]
+pub struct ReportConfig {
+    /// How long a report waits for a moderator before it is dropped.
+    pub ttl: Duration,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(14 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Online guessing limits for access proofs and burn tokens (see
+/// `handlers::verify_secret`), and whether read tokens are mandatory.
+#[derive(Clone, Debug)]
+pub struct AccessConfig {
+    /// Wrong guesses per paste before it locks.
+    pub max_failures: u32,
+    /// A locked paste unlocks this long after the last wrong guess.
+    pub lockout: Duration,
+    /// Delete a paste once too many wrong access proofs were sent, instead of
+    /// locking it. Burn tokens only ever lock.
+    pub destroy: bool,
+    /// Refuse new pastes without a read token, so no paste on the instance
+    /// can be probed or fetched by ID alone.
+    pub require_read_token: bool,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 10,
+            lockout: Duration::from_secs(60 * 60),
+            destroy: false,
+            require_read_token: false,
+        }
+    }
+}
+
+/// Two-phase burn-after-read; see `handlers::get_paste`.
+#[derive(Clone, Debug)]
+pub struct BurnConfig {
+    /// How long the first reader has to acknowledge before the paste can be
+    /// fetched again.
+    pub lease: Duration,
+}
+
+impl Default for BurnConfig {
+    fn default() -> Self {
+        Self {
+            lease: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Hardened mode: paste lookups answer hits and misses in the same time and,
+/// for metadata, the same number of bytes; see `uniform`.
+#[derive(Clone, Debug)]
+pub struct HardeningConfig {
+    pub enabled: bool,
+    /// Lookups are held back until this long after the request arrived. Pick
+    /// something above the slowest lookup, or slow hits stand out again.
+    pub floor: Duration,
+}
+
+impl Default for HardeningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            floor: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Server-side encryption of stored paste records (see `envelope`).
+#[derive(Clone, Default)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    /// Needed when several replicas share one Redis; a random key per
+    /// process otherwise.
+    pub key: Option<[u8; 32]>,
+}
+
+impl fmt::Debug for EncryptionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionConfig")
+            .field("enabled", &self.enabled)
+            .field("key", &self.key.map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RedisConfig {
+    /// Single-node URL; ignored when `sentinel` or `cluster_nodes` is set.
+    pub url: String,
+    pub sentinel: Option<SentinelConfig>,
+    /// Seed nodes of a Redis Cluster; the rest of the topology is discovered.
+    pub cluster_nodes: Option<Vec<String>>,
+    /// Serve reads from replicas (eventually consistent).
+    pub read_from_replicas: bool,
+    /// ACL credentials, overriding any in the URLs.
+    pub auth: Option<RedisAuth>,
+    /// Custom trust and client certificates for `rediss://` links.
+    pub tls: Option<RedisTlsConfig>,
+    /// Prepended to every key, so several instances can share one Redis.
+    pub key_prefix: String,
+    /// Extra attempts at getting a pooled connection before giving up.
+    pub connect_retries: u32,
+    /// Delay before the first retry; doubles on each further attempt.
+    pub retry_backoff: Duration,
+    /// Consecutive failed calls that open the circuit breaker.
+    pub breaker_failure_threshold: u32,
+    /// How long the breaker stays open before letting a probe through.
+    pub breaker_open_for: Duration,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379".to_string(),
+            sentinel: None,
+            cluster_nodes: None,
+            read_from_replicas: false,
+            auth: None,
+            tls: None,
+            key_prefix: String::new(),
+            connect_retries: 2,
+            retry_backoff: Duration::from_millis(50),
+            breaker_failure_threshold: 5,
+            breaker_open_for: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RedisAuth {
+    /// `None` authenticates as the `default` user.
+    pub username: Option<String>,
+    pub password: String,
+}
+
+impl fmt::Debug for RedisAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisAuth")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RedisTlsConfig {
+    /// PEM bundle trusted instead of the system roots.
+    pub ca_path: Option<PathBuf>,
+    /// PEM client certificate and key for mutual TLS.
+    pub client_cert: Option<(PathBuf, PathBuf)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SentinelConfig {
+    /// `redis://host:26379` URLs of the Sentinels to ask.
+    pub urls: Vec<String>,
+    pub master_name: String,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let log_format = match env::var("LOG_FORMAT").as_deref() {
+            Ok("json") => LogFormat::Json,
+            Ok("text") | Err(_) => LogFormat::Text,
+            Ok(_) => return Err(ConfigError("LOG_FORMAT must be text or json".into())),
+        };
+
+        let frontend = match (env::var("FRONTEND_DIR"), env_bool("FRONTEND_EMBEDDED")?) {
+            (Ok(_), Some(true)) => {
+                return Err(ConfigError(
+                    "FRONTEND_DIR and FRONTEND_EMBEDDED are mutually exclusive".into(),
+                ))
+            }
+            (Ok(dir), _) => Some(FrontendSource::Directory(dir.into())),
+            #[cfg(feature = "embed-frontend")]
+            (Err(_), Some(true)) => Some(FrontendSource::Embedded),
+            #[cfg(not(feature = "embed-frontend"))]
+            (Err(_), Some(true)) => {
+                return Err(ConfigError(
+                    "FRONTEND_EMBEDDED requires building with --features embed-frontend".into(),
+                ))
+            }
+            (Err(_), _) => None,
+        };
+
+        let rate_limit = RateLimitConfig::from_env("", &RateLimitConfig::default())?;
+        let paste_ttl = TtlPolicy::from_env("", &TtlPolicy::default())?;
+
+        Ok(Self {
+            log_format,
+            server: ServerConfig::from_env()?,
+            tenants: TenantConfig::all_from_env(&rate_limit, &paste_ttl)?,
+            rate_limit,
+            frontend,
+            security_headers: SecurityHeadersConfig::from_env()?,
+            cors: CorsConfig::from_env()?,
+            timeouts: TimeoutConfig::from_env()?,
+            memory: MemoryConfig::from_env()?,
+            store: StoreConfig::from_env()?,
+            encryption: EncryptionConfig::from_env()?,
+            admin: AdminConfig::from_env()?,
+            reports: ReportConfig::from_env()?,
+            access: AccessConfig::from_env()?,
+            burn: BurnConfig::from_env()?,
+            hardening: HardeningConfig::from_env()?,
+            redis: RedisConfig::from_env()?,
+            paste_ttl,
+        })
+    }
+}
+
+impl ServerConfig {
+    fn from_env() -> Result<Self, ConfigError> {
+        let mut listeners = match env::var("BIND_ADDR") {
+            Ok(specs) => specs
+                .split(',')
+                .map(|spec| {
+                    ListenerConfig::parse(spec)
+                        .map_err(|err| ConfigError(format!("BIND_ADDR: {}", err)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(_) => Self::default().listeners,
+        };
+
+        // PROXY_PROTOCOL=true applies to every listener; `;proxy` picks some.
+        if env_bool("PROXY_PROTOCOL")?.unwrap_or(false) {
+            for listener in &mut listeners {
+                listener.proxy_protocol = true;
+            }
+        }
+
+        let tls = match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+            (Ok(cert_path), Ok(key_path)) => Some(TlsConfig {
+                cert_path: cert_path.into(),
+                key_path: key_path.into(),
+                redirect_from: env::var("TLS_REDIRECT_ADDR")
+                    .ok()
+                    .map(|addr| parse_addr("TLS_REDIRECT_ADDR", &addr))
+                    .transpose()?,
+            }),
+            (Err(_), Err(_)) => None,
+            _ => {
+                return Err(ConfigError(
+                    "TLS_CERT_PATH and TLS_KEY_PATH must be set together".into(),
+                ))
+            }
+        };
+
+        let metrics_addr = env::var("METRICS_ADDR")
+            .ok()
+            .map(|addr| parse_addr("METRICS_ADDR", &addr))
+            .transpose()?;
+
+        let trusted_proxies = match env::var("TRUSTED_PROXIES") {
+            Ok(value) => parse_networks("TRUSTED_PROXIES", &value)?,
+            Err(_) => Vec::new(),
+        };
+
+        Ok(Self {
+            listeners,
+            tls,
+            metrics_addr,
+            trusted_proxies,
+        })
+    }
+}
+
+fn parse_addr(var: &str, value: &str) -> Result<SocketAddr, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError(format!("{} must be an address like 0.0.0.0:8080", var)))
+}
+
+/// Comma-separated CIDRs; a bare address stands for just itself.
+fn parse_networks(var: &str, value: &str) -> Result<Vec<IpNet>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|network| !network.is_empty())
+        .map(|network| {
+            network
+                .parse()
+                .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| {
+                    ConfigError(format!(
+                        "{} must list addresses or CIDRs like 172.30.0.10 or 10.0.0.0/8",
+                        var
+                    ))
+                })
+        })
+        .collect()
+}
+
+impl RateLimitConfig {
+    /// Reads `RATE_LIMIT_ENABLED` and one `RATE_LIMIT_<ROUTE>=<burst>,<per_second>`
+    /// variable per route (e.g. `RATE_LIMIT_READ=200,50`), each with `prefix`
+    /// in front; anything unset is taken from `base`.
+    fn from_env(prefix: &str, base: &Self) -> Result<Self, ConfigError> {
+        let mut config = base.clone();
+
+        if let Some(enabled) = env_bool(&format!("{}RATE_LIMIT_ENABLED", prefix))? {
+            config.enabled = enabled;
+        }
+
+        for route in LimitedRoute::ALL {
+            let var = format!("{}RATE_LIMIT_{}", prefix, route.name().to_uppercase());
+            if let Ok(value) = env::var(&var) {
+                let policy = RateLimitPolicy::parse(&value)
+                    .ok_or_else(|| ConfigError(format!("{} must be <burst>,<per_second>", var)))?;
+                config.policies.insert(route, policy);
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl SecurityHeadersConfig {
+    /// `SECURITY_HEADER_<NAME>=<value>` adds or replaces a header, with `_` in
+    /// the name standing for `-`; an empty value drops it from the set.
+    fn from_env() -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        let mut overrides: Vec<(String, String)> = env::vars()
+            .filter_map(|(var, value)| {
+                let name = var.strip_prefix("SECURITY_HEADER_")?;
+                Some((name.replace('_', "-").to_ascii_lowercase(), value))
+            })
+            .collect();
+        overrides.sort();
+
+        for (name, value) in overrides {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| ConfigError(format!("invalid security header name {:?}", name)))?;
+            config.headers.retain(|(existing, _)| *existing != name);
+            if value.is_empty() {
+                continue;
+            }
+            let value = HeaderValue::from_str(&value)
+                .map_err(|_| ConfigError(format!("invalid value for security header {}", name)))?;
+            config.headers.push((name, value));
+        }
+
+        Ok(config)
+    }
+}
+
+impl CorsConfig {
+    /// `CORS_ALLOWED_ORIGINS` is a comma-separated list of origins, each
+    /// optionally starting with a `*.` wildcard label. The older single-origin
+    /// `FRONTEND_URL` is still honoured when the list is unset.
+    fn from_env() -> Result<Self, ConfigError> {
+        let (var, value) = match env::var("CORS_ALLOWED_ORIGINS") {
+            Ok(value) => ("CORS_ALLOWED_ORIGINS", value),
+            Err(_) => match env::var("FRONTEND_URL") {
+                Ok(value) => ("FRONTEND_URL", value),
+                Err(_) => return Ok(Self::default()),
+            },
+        };
+
+        Ok(Self {
+            allowed_origins: parse_origins(var, &value)?,
+        })
+    }
+}
+
+fn parse_origins(var: &str, value: &str) -> Result<Vec<OriginPattern>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            OriginPattern::parse(origin).map_err(|err| ConfigError(format!("{}: {}", var, err)))
+        })
+        .collect()
+}
+
+impl TtlPolicy {
+    /// `PASTE_DEFAULT_TTL_SECS` and `PASTE_MAX_TTL_SECS` with `prefix` in
+    /// front; anything unset is taken from `base`.
+    fn from_env(prefix: &str, base: &Self) -> Result<Self, ConfigError> {
+        let default_var = format!("{}PASTE_DEFAULT_TTL_SECS", prefix);
+        let max_var = format!("{}PASTE_MAX_TTL_SECS", prefix);
+        let policy = Self {
+            default: env_secs(&default_var)?.unwrap_or(base.default),
+            max: env_secs(&max_var)?.unwrap_or(base.max),
+        };
+        if policy.default > policy.max {
+            return Err(ConfigError(format!(
+                "{} must not exceed {}",
+                default_var, max_var
+            )));
+        }
+        Ok(policy)
+    }
+}
+
+impl TenantConfig {
+    /// `TENANTS` is a comma-separated list of tenant names. Each needs
+    /// `TENANT_<NAME>_HOSTS` (comma-separated) and may override
+    /// `RATE_LIMIT_*`, `PASTE_*_TTL_SECS` and `CORS_ALLOWED_ORIGINS` as
+    /// `TENANT_<NAME>_RATE_LIMIT_CREATE` etc. CORS defaults to the tenant's
+    /// own hosts over https (http for `.onion`).
+    fn all_from_env(
+        rate_limit: &RateLimitConfig,
+        paste_ttl: &TtlPolicy,
+    ) -> Result<Vec<Self>, ConfigError> {
+        let Ok(names) = env::var("TENANTS") else {
+            return Ok(Vec::new());
+        };
+
+        let mut tenants: Vec<Self> = Vec::new();
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let name = name.to_ascii_lowercase();
+            if !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                return Err(ConfigError(format!(
+                    "TENANTS: {:?} may only contain letters, digits and '-'",
+                    name
+                )));
+            }
+            if tenants.iter().any(|tenant| tenant.name == name) {
+                return Err(ConfigError(format!("TENANTS: {:?} is listed twice", name)));
+            }
+            let prefix = format!("TENANT_{}_", name.to_uppercase().replace('-', "_"));
+
+            let hosts_var = format!("{}HOSTS", prefix);
+            let hosts: Vec<String> = env::var(&hosts_var)
+                .unwrap_or_default()
+                .split(',')
+                .map(|host| host.trim().to_ascii_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect();
+            if hosts.is_empty() {
+                return Err(ConfigError(format!(
+                    "{} must list at least one host",
+                    hosts_var
+                )));
+            }
+            for host in &hosts {
+                if tenants.iter().any(|tenant| tenant.hosts.contains(host)) {
+                    return Err(ConfigError(format!(
+                        "{}: {} already belongs to another tenant",
+                        hosts_var, host
+                    )));
+                }
+            }
+
+            let cors_var = format!("{}CORS_ALLOWED_ORIGINS", prefix);
+            let allowed_origins = match env::var(&cors_var) {
+                Ok(value) => parse_origins(&cors_var, &value)?,
+                Err(_) => hosts
+                    .iter()
+                    .map(|host| {
+                        let scheme = if host.ends_with(".onion") {
+                            "http"
+                        } else {
+                            "https"
+                        };
+                        OriginPattern::parse(&format!("{}://{}", scheme, host))
+                            .map_err(|err| ConfigError(format!("{}: {}", hosts_var, err)))
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+
+            tenants.push(Self {
+                rate_limit: RateLimitConfig::from_env(&prefix, rate_limit)?,
+                paste_ttl: TtlPolicy::from_env(&prefix, paste_ttl)?,
+                cors: CorsConfig { allowed_origins },
+                name,
+                hosts,
+            });
+        }
+        Ok(tenants)
+    }
+}
+
+impl TimeoutConfig {
+    /// `TIMEOUT_HEADER_READ_MS`, `TIMEOUT_BODY_READ_MS`, `TIMEOUT_REQUEST_MS`
+    /// and `TIMEOUT_REDIS_COMMAND_MS`.
+    fn from_env() -> Result<Self, ConfigError> {
+        let defaults = Self::default();
+        let config = Self {
+            header_read: env_millis("TIMEOUT_HEADER_READ_MS")?.unwrap_or(defaults.header_read),
+            body_read: env_millis("TIMEOUT_BODY_READ_MS")?.unwrap_or(defaults.body_read),
+            request: env_millis("TIMEOUT_REQUEST_MS")?.unwrap_or(defaults.request),
+            redis_command: env_millis("TIMEOUT_REDIS_COMMAND_MS")?
+                .unwrap_or(defaults.redis_command),
+        };
+
+        // The body is read inside the request deadline; a longer body timeout
+        // would turn slow clients into 503s instead of 408s.
+        if config.body_read > config.request {
+            return Err(ConfigError(
+                "TIMEOUT_BODY_READ_MS must not exceed TIMEOUT_REQUEST_MS".into(),
+            ));
+        }
+        Ok(config)
+    }
+}
+
+impl MemoryConfig {
+    /// `REDIS_MEMORY_HIGH_WATER_PERCENT`, `REDIS_MEMORY_LIMIT_BYTES` and
+    /// `REDIS_MEMORY_POLL_MS`.
+    fn from_env() -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        if let Ok(value) = env::var("REDIS_MEMORY_HIGH_WATER_PERCENT") {
+            let percent: f64 = value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|p| *p > 0.0 && *p <= 100.0)
+                .ok_or_else(|| {
+                    ConfigError("REDIS_MEMORY_HIGH_WATER_PERCENT must be in (0, 100]".into())
+                })?;
+            config.high_water_ratio = percent / 100.0;
+        }
+        if let Ok(value) = env::var("REDIS_MEMORY_LIMIT_BYTES") {
+            config.limit_bytes = Some(value.trim().parse().ok().filter(|b| *b > 0).ok_or_else(
+                || ConfigError("REDIS_MEMORY_LIMIT_BYTES must be a positive integer".into()),
+            )?);
+        }
+        if let Some(interval) = env_millis("REDIS_MEMORY_POLL_MS")? {
+            config.poll_interval = interval;
+        }
+
+        Ok(config)
+    }
+}
+
+impl StoreConfig {
+    /// `STORE` (`redis` or `embedded`), and for the latter
+    /// `EMBEDDED_STORE_BYTES`, `EMBEDDED_STORE_SHARDS` and
+    /// `EMBEDDED_STORE_MLOCK`.
+    fn from_env() -> Result<Self, ConfigError> {
+        match env::var("STORE").as_deref() {
+            Ok("redis") | Err(_) => Ok(Self::Redis),
+            #[cfg(feature = "embedded-store")]
+            Ok("embedded") => Ok(Self::Embedded(EmbeddedStoreConfig::from_env()?)),
+            #[cfg(not(feature = "embedded-store"))]
+            Ok("embedded") => Err(ConfigError(
+                "STORE=embedded requires building with --features embedded-store".into(),
+            )),
+            Ok(_) => Err(ConfigError("STORE must be redis or embedded".into())),
+        }
+    }
+}
+
+impl EmbeddedStoreConfig {
+    #[cfg_attr(not(feature = "embedded-store"), allow(dead_code))]
+    fn from_env() -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        if let Ok(value) = env::var("EMBEDDED_STORE_BYTES") {
+            config.budget_bytes =
+                value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|b| *b > 0)
+                    .ok_or_else(|| {
+                        ConfigError("EMBEDDED_STORE_BYTES must be a positive integer".into())
+                    })?;
+        }
+        if let Some(shards) = env_u32("EMBEDDED_STORE_SHARDS")? {
+            if shards == 0 {
+                return Err(ConfigError("EMBEDDED_STORE_SHARDS must be positive".into()));
+            }
+            config.shards = shards as usize;
+        }
+        if let Some(mlock) = env_bool("EMBEDDED_STORE_MLOCK")? {
+            config.mlock = mlock;
+        }
+
+        Ok(config)
+    }
+}
+
+impl AdminConfig {
+    /// `ADMIN_TOKEN` (or `ADMIN_TOKEN_FILE`), `ADMIN_ADDR`, `AUDIT_LOG_PATH`
+    /// and `WIPE_SIGNAL` (`SIGUSR1` or `SIGUSR2`).
+    fn from_env() -> Result<Self, ConfigError> {
+        let token = env_secret("ADMIN_TOKEN")?;
+        if token.as_ref().is_some_and(|token| token.len() < 32) {
+            return Err(ConfigError(
+                "ADMIN_TOKEN must be at least 32 characters".into(),
+            ));
+        }
+        let wipe_signal = match env::var("WIPE_SIGNAL").as_deref() {
+            Ok("SIGUSR1" | "USR1") => Some(SignalKind::user_defined1()),
+            Ok("SIGUSR2" | "USR2") => Some(SignalKind::user_defined2()),
+            Ok(_) => return Err(ConfigError("WIPE_SIGNAL must be SIGUSR1 or SIGUSR2".into())),
+            Err(_) => None,
+        };
+        let bind = env::var("ADMIN_ADDR")
+            .ok()
+            .map(|addr| parse_addr("ADMIN_ADDR", &addr))
+            .transpose()?;
+        let audit_log_path = env::var("AUDIT_LOG_PATH").ok().map(PathBuf::from);
+        // Takedowns must be recorded with the IDs they concern, which have no
+        // place in the request log.
+        if (token.is_some() || bind.is_some()) && audit_log_path.is_none() {
+            return Err(ConfigError(
+                "AUDIT_LOG_PATH is required with ADMIN_TOKEN or ADMIN_ADDR".into(),
+            ));
+        }
+        Ok(Self {
+            token,
+            bind,
+            audit_log_path,
+            wipe_signal,
+        })
+    }
+}
+
+impl ReportConfig {
+    /// `REPORT_TTL_SECS`.
+    fn from_env() -> Result<Self, ConfigError> {
+        let defaults = Self::default();
+        Ok(Self {
+            ttl: env_secs("REPORT_TTL_SECS")?.unwrap_or(defaults.ttl),
+        })
+    }
+}
+
+impl AccessConfig {
+    /// `ACCESS_MAX_FAILURES`, `ACCESS_LOCKOUT_SECS`,
+    /// `ACCESS_DESTROY_ON_LOCKOUT` and `REQUIRE_READ_TOKEN`.
+    fn from_env() -> Result<Self, ConfigError> {
+        let defaults = Self::default();
+        let max_failures = env_u32("ACCESS_MAX_FAILURES")?.unwrap_or(defaults.max_failures);
+        if max_failures == 0 {
+            return Err(ConfigError("ACCESS_MAX_FAILURES must be at least 1".into()));
+        }
+        Ok(Self {
+            max_failures,
+            lockout: env_secs("ACCESS_LOCKOUT_SECS")?.unwrap_or(defaults.lockout),
+            destroy: env_bool("ACCESS_DESTROY_ON_LOCKOUT")?.unwrap_or(defaults.destroy),
+            require_read_token: env_bool("REQUIRE_READ_TOKEN")?
+                .unwrap_or(defaults.require_read_token),
+        })
+    }
+}
+
+impl BurnConfig {
+    /// `BURN_LEASE_SECS`.
+    fn from_env() -> Result<Self, ConfigError> {
+        let defaults = Self::default();
+        Ok(Self {
+            lease: env_secs("BURN_LEASE_SECS")?.unwrap_or(defaults.lease),
+        })
+    }
+}
+
+impl HardeningConfig {
+    /// `HARDENED_MODE` and `HARDENED_FLOOR_MS`.
+    fn from_env() -> Result<Self, ConfigError> {
+        let defaults = Self::default();
+        Ok(Self {
+            enabled: env_bool("HARDENED_MODE")?.unwrap_or(defaults.enabled),
+            floor: env_millis("HARDENED_FLOOR_MS")?.unwrap_or(defaults.floor),
+        })
+    }
+}
+
+impl EncryptionConfig {
+    /// `STORE_ENCRYPTION` and `STORE_ENCRYPTION_KEY` (or
+    /// `STORE_ENCRYPTION_KEY_FILE`), 64 hex digits; a key implies the former.
+    fn from_env() -> Result<Self, ConfigError> {
+        let key = env_secret("STORE_ENCRYPTION_KEY")?
+            .map(|key| {
+                let mut bytes = [0u8; 32];
+                hex::decode_to_slice(key.trim(), &mut bytes)
+                    .map(|_| bytes)
+                    .map_err(|_| ConfigError("STORE_ENCRYPTION_KEY must be 64 hex digits".into()))
+            })
+            .transpose()?;
+        let enabled = match (env_bool("STORE_ENCRYPTION")?, key) {
+            (Some(false), Some(_)) => {
+                return Err(ConfigError(
+                    "STORE_ENCRYPTION_KEY is set but STORE_ENCRYPTION is false".into(),
+                ))
+            }
+            (Some(enabled), _) => enabled,
+            (None, key) => key.is_some(),
+        };
+        Ok(Self { enabled, key })
+    }
+}
+
+impl RedisConfig {
+    /// `REDIS_URL`, `REDIS_KEY_PREFIX`, `REDIS_SENTINELS` (comma-separated) with
+    /// `REDIS_SENTINEL_MASTER`, or `REDIS_CLUSTER_NODES` (comma-separated);
+    /// plus `REDIS_READ_FROM_REPLICAS`, `REDIS_USERNAME`, `REDIS_PASSWORD`
+    /// (or `REDIS_PASSWORD_FILE`), `REDIS_TLS_CA_PATH`, `REDIS_TLS_CERT_PATH`,
+    /// `REDIS_TLS_KEY_PATH`,
+    /// `REDIS_CONNECT_RETRIES`, `REDIS_RETRY_BACKOFF_MS`,
+    /// `REDIS_BREAKER_THRESHOLD` and `REDIS_BREAKER_OPEN_MS`.
+    fn from_env() -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        if let Ok(url) = env::var("REDIS_URL") {
+            config.url = url;
+        }
+        if let Ok(prefix) = env::var("REDIS_KEY_PREFIX") {
+            if prefix.contains(['{', '}']) || prefix.chars().any(char::is_whitespace) {
+                return Err(ConfigError(
+                    "REDIS_KEY_PREFIX must not contain braces or whitespace".into(),
+                ));
+            }
+            config.key_prefix = prefix;
+        }
+        if let Some(urls) = env_redis_urls("REDIS_SENTINELS")? {
+            config.sentinel = Some(SentinelConfig {
+                urls,
+                master_name: env::var("REDIS_SENTINEL_MASTER")
+                    .unwrap_or_else(|_| "ghostbin".to_string()),
+            });
+        }
+        config.cluster_nodes = env_redis_urls("REDIS_CLUSTER_NODES")?;
+        if config.sentinel.is_some() && config.cluster_nodes.is_some() {
+            return Err(ConfigError(
+                "REDIS_SENTINELS and REDIS_CLUSTER_NODES are mutually exclusive".into(),
+            ));
+        }
+        if let Some(replicas) = env_bool("REDIS_READ_FROM_REPLICAS")? {
+            if replicas && config.sentinel.is_none() && config.cluster_nodes.is_none() {
+                return Err(ConfigError(
+                    "REDIS_READ_FROM_REPLICAS needs REDIS_SENTINELS or REDIS_CLUSTER_NODES to find replicas".into(),
+                ));
+            }
+            config.read_from_replicas = replicas;
+        }
+
+        let username = env_secret("REDIS_USERNAME")?;
+        match (username, env_secret("REDIS_PASSWORD")?) {
+            (username, Some(password)) => config.auth = Some(RedisAuth { username, password }),
+            (Some(_), None) => {
+                return Err(ConfigError(
+                    "REDIS_USERNAME needs REDIS_PASSWORD or REDIS_PASSWORD_FILE".into(),
+                ))
+            }
+            (None, None) => {}
+        }
+
+        let client_cert = match (
+            env::var("REDIS_TLS_CERT_PATH"),
+            env::var("REDIS_TLS_KEY_PATH"),
+        ) {
+            (Ok(cert), Ok(key)) => Some((PathBuf::from(cert), PathBuf::from(key))),
+            (Err(_), Err(_)) => None,
+            _ => {
+                return Err(ConfigError(
+                    "REDIS_TLS_CERT_PATH and REDIS_TLS_KEY_PATH must be set together".into(),
+                ))
+            }
+        };
+        let ca_path = env::var("REDIS_TLS_CA_PATH").ok().map(PathBuf::from);
+        if ca_path.is_some() || client_cert.is_some() {
+            if config.sentinel.is_some() || config.cluster_nodes.is_some() {
+                return Err(ConfigError(
+                    "REDIS_TLS_CA_PATH and client certificates are only supported with REDIS_URL; \
+                     use rediss:// URLs with a system-trusted CA for Sentinel or Cluster"
+                        .into(),
+                ));
+            }
+            if !config.url.starts_with("rediss://") {
+                return Err(ConfigError(
+                    "REDIS_TLS_* settings need a rediss:// REDIS_URL".into(),
+                ));
+            }
+            config.tls = Some(RedisTlsConfig {
+                ca_path,
+                client_cert,
+            });
+        }
+
+        if let Some(retries) = env_u32("REDIS_CONNECT_RETRIES")? {
+            config.connect_retries = retries;
+        }
+        if let Some(backoff) = env_millis("REDIS_RETRY_BACKOFF_MS")? {
+            config.retry_backoff = backoff;
+        }
+        if let Some(threshold) = env_u32("REDIS_BREAKER_THRESHOLD")? {
+            if threshold == 0 {
+                return Err(ConfigError(
+                    "REDIS_BREAKER_THRESHOLD must be at least 1".into(),
+                ));
+            }
+            config.breaker_failure_threshold = threshold;
+        }
+        if let Some(open_for) = env_millis("REDIS_BREAKER_OPEN_MS")? {
+            config.breaker_open_for = open_for;
+        }
+
+        Ok(config)
+    }
+}
+
+fn env_u32(var: &str) -> Result<Option<u32>, ConfigError> {
+    match env::var(var) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError(format!("{} must be a non-negative integer", var))),
+        Err(_) => Ok(None),
+    }
+}
+
+fn env_millis(var: &str) -> Result<Option<Duration>, ConfigError> {
+    match env::var(var) {
+        Ok(value) => match value.trim().parse::<u64>() {
+            Ok(ms) if ms > 0 => Ok(Some(Duration::from_millis(ms))),
+            _ => Err(ConfigError(format!(
+                "{} must be a positive number of milliseconds",
+                var
+            ))),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+fn env_secs(var: &str) -> Result<Option<Duration>, ConfigError> {
+    match env::var(var) {
+        Ok(value) => match value.trim().parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Some(Duration::from_secs(secs))),
+            _ => Err(ConfigError(format!(
+                "{} must be a positive number of seconds",
+                var
+            ))),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// `VAR`, or the contents of the file named by `VAR_FILE` (e.g. a Docker
+/// secret) with one trailing newline removed.
+fn env_secret(var: &str) -> Result<Option<String>, ConfigError> {
+    let file_var = format!("{}_FILE", var);
+    match (env::var(var), env::var(&file_var)) {
+        (Ok(_), Ok(_)) => Err(ConfigError(format!(
+            "{} and {} are mutually exclusive",
+            var, file_var
+        ))),
+        (Ok(value), Err(_)) => Ok(Some(value)),
+        (Err(_), Ok(path)) => {
+            let value = std::fs::read_to_string(&path).map_err(|err| {
+                ConfigError(format!("{}: cannot read {}: {}", file_var, path, err))
+            })?;
+            let value = value
+                .strip_suffix('\n')
+                .map(|value| value.strip_suffix('\r').unwrap_or(value))
+                .unwrap_or(&value);
+            Ok(Some(value.to_string()))
+        }
+        (Err(_), Err(_)) => Ok(None),
+    }
+}
+
+/// Comma-separated `host:port` or `redis://` URLs; `redis://` is assumed
+/// when no scheme is given.
+fn env_redis_urls(var: &str) -> Result<Option<Vec<String>>, ConfigError> {
+    let Ok(value) = env::var(var) else {
+        return Ok(None);
+    };
+    let urls: Vec<String> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(|url| {
+            if url.contains("://") {
+                url.to_string()
+            } else {
+                format!("redis://{}", url)
+            }
+        })
+        .collect();
+    if urls.is_empty() {
+        return Err(ConfigError(format!("{} must list at least one node", var)));
+    }
+    Ok(Some(urls))
+}
+
+fn env_bool(var: &str) -> Result<Option<bool>, ConfigError> {
+    match env::var(var) {
+        Ok(value) => match value.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Some(true)),
+            "0" | "false" | "no" | "off" => Ok(Some(false)),
+            _ => Err(ConfigError(format!("{} must be a boolean", var))),
+        },
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,163 @@
+use crate::tenant::Tenant;
+use axum::http::{header, request::Parts, HeaderValue, Method};
+use std::sync::Arc;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// One entry of `CORS_ALLOWED_ORIGINS`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OriginPattern {
+    /// `https://ghostbin.example` — matched exactly, ignoring ASCII case.
+    Exact(String),
+    /// `https://*.ghostbin.example` — any subdomain at any depth, but not the
+    /// bare domain itself.
+    Subdomains {
+        scheme: String,
+        /// Starts with `.`, e.g. `.ghostbin.example:8443`.
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid origin {:?}", value);
+
+        let (scheme, authority) = value.split_once("://").ok_or_else(invalid)?;
+        if scheme != "http" && scheme != "https" {
+            return Err(format!("origin {:?} must use http or https", value));
+        }
+        if authority.is_empty() || authority.contains(['/', '?', '#', '@']) {
+            return Err(format!(
+                "origin {:?} must not have a path or userinfo",
+                value
+            ));
+        }
+
+        let (host, port) = match authority.rsplit_once(':') {
+            // The bracket check keeps IPv6 literals intact.
+            Some((host, port)) if !port.contains(']') => {
+                port.parse::<u16>().map_err(|_| invalid())?;
+                (host, Some(port))
+            }
+            _ => (authority, None),
+        };
+
+        match host.strip_prefix("*.") {
+            Some(domain) => {
+                if domain.is_empty() || domain.contains('*') || !domain.contains('.') {
+                    return Err(format!("wildcard origin {:?} is too broad", value));
+                }
+                let mut suffix = format!(".{}", domain.to_ascii_lowercase());
+                if let Some(port) = port {
+                    suffix = format!("{}:{}", suffix, port);
+                }
+                Ok(OriginPattern::Subdomains {
+                    scheme: scheme.to_string(),
+                    suffix,
+                })
+            }
+            None if host.contains('*') => Err(format!(
+                "origin {:?}: `*` is only allowed as the first label",
+                value
+            )),
+            None => {
+                HeaderValue::from_str(value).map_err(|_| invalid())?;
+                Ok(OriginPattern::Exact(value.to_ascii_lowercase()))
+            }
+        }
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        match self {
+            OriginPattern::Exact(allowed) => origin.eq_ignore_ascii_case(allowed),
+            OriginPattern::Subdomains { scheme, suffix } => {
+                let origin = origin.to_ascii_lowercase();
+                let Some(host) = origin
+                    .strip_prefix(scheme.as_str())
+                    .and_then(|rest| rest.strip_prefix("://"))
+                else {
+                    return false;
+                };
+                host.strip_suffix(suffix.as_str())
+                    .is_some_and(is_subdomain_labels)
+            }
+        }
+    }
+}
+
+/// `a` or `a.b`, never empty and never containing a port or path.
+fn is_subdomain_labels(labels: &str) -> bool {
+    !labels.is_empty()
+        && labels.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+/// CORS policy for the API, allowing the origins of the request's tenant
+/// (see `tenant::resolve`). `methods` should be exactly the methods the
+/// router answers to.
+pub fn layer(methods: Vec<Method>) -> CorsLayer {
+    let allow_origin = AllowOrigin::predicate(|origin: &HeaderValue, parts: &Parts| {
+        let Some(tenant) = parts.extensions.get::<Arc<Tenant>>() else {
+            return false;
+        };
+        origin.to_str().is_ok_and(|origin| {
+            tenant
+                .cors
+                .allowed_origins
+                .iter()
+                .any(|pattern| pattern.matches(origin))
+        })
+    });
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(Any)
+        // The SPA backs off on 429/503 using this.
+        .expose_headers([header::RETRY_AFTER])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_origin() {
+        let pattern = OriginPattern::parse("http://abcdefgh.onion").unwrap();
+        assert!(pattern.matches("http://abcdefgh.onion"));
+        assert!(!pattern.matches("https://abcdefgh.onion"));
+        assert!(!pattern.matches("http://abcdefgh.onion.evil.example"));
+    }
+
+    #[test]
+    fn test_wildcard_subdomains() {
+        let pattern = OriginPattern::parse("https://*.ghostbin.example").unwrap();
+        assert!(pattern.matches("https://www.ghostbin.example"));
+        assert!(pattern.matches("https://a.b.ghostbin.example"));
+        assert!(!pattern.matches("https://ghostbin.example"));
+        assert!(!pattern.matches("https://evilghostbin.example"));
+        assert!(!pattern.matches("https://www.ghostbin.example:8443"));
+        assert!(!pattern.matches("http://www.ghostbin.example"));
+
+        let with_port = OriginPattern::parse("http://*.localhost.test:3000").unwrap();
+        assert!(with_port.matches("http://dev.localhost.test:3000"));
+    }
+
+    #[test]
+    fn test_rejects_bad_origins() {
+        for bad in [
+            "ghostbin.example",
+            "ftp://ghostbin.example",
+            "https://ghostbin.example/",
+            "https://*.example",
+            "https://*",
+            "https://a.*.example.org",
+            "https://ghostbin.example:http",
+        ] {
+            assert!(OriginPattern::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -1,8 +1,492 @@
-use deadpool_redis::{Config, Pool, Runtime};
-use std::env;
+use crate::config::{RedisAuth, RedisConfig, RedisTlsConfig, SentinelConfig};
+#[cfg(feature = "embedded-store")]
+use crate::embedded_store::EmbeddedStore;
+use crate::error::AppError;
+use crate::keys::Keys;
+use anyhow::Context;
+use deadpool_redis::cluster;
+use deadpool_redis::redis::aio::ConnectionLike;
+use deadpool_redis::redis::cluster_routing::{RoutingInfo, SingleNodeRoutingInfo};
+use deadpool_redis::redis::{
+    from_redis_value, Client, ClientTlsConfig, Cmd, ConnectionInfo, ErrorKind, IntoConnectionInfo,
+    Pipeline, RedisError, RedisFuture, TlsCertificates, Value,
+};
+use deadpool_redis::sentinel::{self, SentinelServerType};
+use deadpool_redis::{PoolError, Runtime};
+use std::future::Future;
+use std::path::Path;
+#[cfg(feature = "embedded-store")]
+use std::sync::Arc;
+use std::time::Duration;
 
-pub fn create_pool() -> Result<Pool, deadpool_redis::CreatePoolError> {
-    let redis_url = env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
-    let cfg = Config::from_url(redis_url);
-    cfg.create_pool(Some(Runtime::Tokio1))
+/// What the repository and rate limiter keep their keys in.
+#[derive(Clone)]
+pub enum Store {
+    Redis(Pool),
+    #[cfg(feature = "embedded-store")]
+    Embedded(Arc<EmbeddedStore>),
+}
+
+/// Connection pools for whichever Redis deployment is configured.
+#[derive(Clone)]
+pub enum Pool {
+    Single(deadpool_redis::Pool),
+    /// Primary discovered through Sentinel, so a failover is followed on the
+    /// next new connection. `replicas` is only set when replica reads are on.
+    Sentinel {
+        primary: sentinel::Pool,
+        replicas: Option<sentinel::Pool>,
+    },
+    /// Commands are routed to the node owning each key's slot; see
+    /// `crate::keys` for how related keys are kept on one slot. `replicas`
+    /// routes reads to replicas and is only set when replica reads are on.
+    Cluster {
+        primary: cluster::Pool,
+        replicas: Option<cluster::Pool>,
+    },
+}
+
+pub enum Connection {
+    Single(deadpool_redis::Connection),
+    Sentinel(sentinel::Connection),
+    Cluster(cluster::Connection),
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Connection::Single(con) => con.req_packed_command(cmd),
+            Connection::Sentinel(con) => con.req_packed_command(cmd),
+            Connection::Cluster(con) => con.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Connection::Single(con) => con.req_packed_commands(cmd, offset, count),
+            Connection::Sentinel(con) => con.req_packed_commands(cmd, offset, count),
+            Connection::Cluster(con) => con.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Connection::Single(con) => con.get_db(),
+            Connection::Sentinel(con) => con.get_db(),
+            Connection::Cluster(con) => con.get_db(),
+        }
+    }
+}
+
+impl From<deadpool_redis::Pool> for Pool {
+    fn from(pool: deadpool_redis::Pool) -> Self {
+        Pool::Single(pool)
+    }
+}
+
+impl Pool {
+    /// Connection to the primary.
+    pub async fn get(&self) -> Result<Connection, PoolError> {
+        match self {
+            Pool::Single(pool) => pool.get().await.map(Connection::Single),
+            Pool::Sentinel { primary, .. } => primary.get().await.map(Connection::Sentinel),
+            Pool::Cluster { primary, .. } => primary.get().await.map(Connection::Cluster),
+        }
+    }
+
+    /// Connection to a replica, or `None` when replica reads are off.
+    pub async fn get_replica(&self) -> Option<Result<Connection, PoolError>> {
+        match self {
+            Pool::Sentinel {
+                replicas: Some(replicas),
+                ..
+            } => Some(replicas.get().await.map(Connection::Sentinel)),
+            Pool::Cluster {
+                replicas: Some(replicas),
+                ..
+            } => Some(replicas.get().await.map(Connection::Cluster)),
+            _ => None,
+        }
+    }
+}
+
+pub fn create_pool(config: &RedisConfig) -> anyhow::Result<Pool> {
+    if let Some(nodes) = &config.cluster_nodes {
+        let primary = cluster_pool(config, nodes, false)?;
+        let replicas = if config.read_from_replicas {
+            Some(cluster_pool(config, nodes, true)?)
+        } else {
+            None
+        };
+        return Ok(Pool::Cluster { primary, replicas });
+    }
+    match &config.sentinel {
+        None => Ok(Pool::Single(single_pool(config)?)),
+        Some(sentinel) => {
+            let primary = sentinel_pool(config, sentinel, SentinelServerType::Master)?;
+            let replicas = if config.read_from_replicas {
+                Some(sentinel_pool(
+                    config,
+                    sentinel,
+                    SentinelServerType::Replica,
+                )?)
+            } else {
+                None
+            };
+            Ok(Pool::Sentinel { primary, replicas })
+        }
+    }
+}
+
+fn single_pool(config: &RedisConfig) -> anyhow::Result<deadpool_redis::Pool> {
+    let info = connection_info(&config.url, config.auth.as_ref())?;
+    // deadpool's own config cannot carry certificates, so build the client
+    // here and hand its resolved connection info to the manager.
+    let client = match &config.tls {
+        Some(tls) => Client::build_with_tls(info, tls_certificates(tls)?)?,
+        None => Client::open(info)?,
+    };
+    let manager = deadpool_redis::Manager::new(client.get_connection_info().clone())?;
+    Ok(deadpool_redis::Pool::builder(manager)
+        .runtime(Runtime::Tokio1)
+        .build()?)
+}
+
+fn cluster_pool(
+    config: &RedisConfig,
+    nodes: &[String],
+    read_from_replicas: bool,
+) -> anyhow::Result<cluster::Pool> {
+    let nodes = nodes
+        .iter()
+        .map(|node| connection_info(node, config.auth.as_ref()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let manager = cluster::Manager::new(nodes, read_from_replicas)?;
+    Ok(cluster::Pool::builder(manager)
+        .runtime(Runtime::Tokio1)
+        .build()?)
+}
+
+fn sentinel_pool(
+    config: &RedisConfig,
+    sentinel: &SentinelConfig,
+    server_type: SentinelServerType,
+) -> anyhow::Result<sentinel::Pool> {
+    // Credentials in the Sentinel URLs are for the Sentinels; `auth` is for
+    // the primary and replicas they point at, which use TLS when the
+    // Sentinels do.
+    let node = sentinel::SentinelNodeConnectionInfo {
+        tls_mode: sentinel
+            .urls
+            .iter()
+            .any(|url| url.starts_with("rediss://"))
+            .then_some(sentinel::TlsMode::Secure),
+        redis_connection_info: config.auth.as_ref().map(|auth| {
+            deadpool_redis::RedisConnectionInfo {
+                username: auth.username.clone(),
+                password: Some(auth.password.clone()),
+                ..Default::default()
+            }
+        }),
+    };
+    let mut builder = sentinel::Config::from_urls(
+        sentinel.urls.clone(),
+        sentinel.master_name.clone(),
+        server_type,
+    )
+    .with_node_connection_info(Some(node))
+    .builder()?
+    .runtime(Runtime::Tokio1);
+    if matches!(server_type, SentinelServerType::Master) {
+        // A demoted primary still answers PING, so check the role too or
+        // pooled connections keep writing to what is now a replica.
+        builder = builder.post_recycle(sentinel::Hook::async_fn(|con, _| {
+            Box::pin(async move {
+                let role: Vec<Value> = deadpool_redis::redis::cmd("ROLE")
+                    .query_async(con)
+                    .await
+                    .map_err(sentinel::HookError::Backend)?;
+                match role.first() {
+                    Some(Value::BulkString(role)) if role == b"master" => Ok(()),
+                    _ => Err(sentinel::HookError::message("no longer the primary")),
+                }
+            })
+        }));
+    }
+    Ok(builder.build()?)
+}
+
+fn connection_info(url: &str, auth: Option<&RedisAuth>) -> anyhow::Result<ConnectionInfo> {
+    let mut info = url.into_connection_info()?;
+    if let Some(auth) = auth {
+        info.redis.username = auth.username.clone();
+        info.redis.password = Some(auth.password.clone());
+    }
+    Ok(info)
+}
+
+fn tls_certificates(tls: &RedisTlsConfig) -> anyhow::Result<TlsCertificates> {
+    let read = |path: &Path| {
+        std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))
+    };
+    let client_tls = match &tls.client_cert {
+        Some((cert, key)) => Some(ClientTlsConfig {
+            client_cert: read(cert)?,
+            client_key: read(key)?,
+        }),
+        None => None,
+    };
+    Ok(TlsCertificates {
+        client_tls,
+        root_cert: tls.ca_path.as_deref().map(read).transpose()?,
+    })
+}
+
+/// Startup check that Redis accepts our credentials and lets us touch paste
+/// keys. An unreachable Redis is only logged: retries and the circuit
+/// breaker deal with that once we are serving.
+pub async fn check_access(pool: &Pool, keys: &Keys, timeout: Duration) -> anyhow::Result<()> {
+    let probe = async {
+        let mut con = pool.get().await?;
+        deadpool_redis::redis::cmd("EXISTS")
+            .arg(keys.paste("startup-check"))
+            .query_async::<()>(&mut con)
+            .await?;
+        Ok::<_, PoolError>(())
+    };
+    match tokio::time::timeout(timeout, probe).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(PoolError::Backend(err))) if is_access_denied(&err) => {
+            Err(anyhow::anyhow!("Redis refused access: {}", err))
+        }
+        Ok(Err(err)) => {
+            tracing::warn!(error = ?err, "Redis not reachable at startup");
+            Ok(())
+        }
+        Err(_) => {
+            tracing::warn!("timed out reaching Redis at startup");
+            Ok(())
+        }
+    }
+}
+
+/// Keys per `SCAN` step and per `UNLINK`.
+const SCAN_BATCH: usize = 500;
+
+/// Walks every key starting with a prefix, `SCAN`ning in batches so no single
+/// command blocks Redis, and on every primary of a cluster. Each command gets
+/// `timeout` on its own, so large keyspaces still finish.
+struct PrefixScan {
+    con: Connection,
+    pattern: String,
+    /// Primaries still to scan; a single `None` outside cluster mode.
+    nodes: Vec<Option<(String, u16)>>,
+    cursor: u64,
+    timeout: Duration,
+}
+
+impl PrefixScan {
+    async fn new(pool: &Pool, prefix: &str, timeout: Duration) -> Result<Self, AppError> {
+        let mut con = timed(timeout, pool.get()).await??;
+        let mut nodes: Vec<_> = match &mut con {
+            Connection::Cluster(cluster) => {
+                let nodes: String = timed(
+                    timeout,
+                    cluster.route_command(
+                        &deadpool_redis::redis::cmd("CLUSTER").arg("NODES").clone(),
+                        RoutingInfo::SingleNode(SingleNodeRoutingInfo::Random),
+                    ),
+                )
+                .await?
+                .and_then(|reply| from_redis_value(&reply))?;
+                cluster_primaries(&nodes).into_iter().map(Some).collect()
+            }
+            _ => vec![None],
+        };
+        nodes.reverse();
+        Ok(Self {
+            con,
+            pattern: format!("{}*", escape_glob(prefix)),
+            nodes,
+            cursor: 0,
+            timeout,
+        })
+    }
+
+    /// The next batch of keys, possibly empty; `None` once every node is done.
+    async fn next_batch(&mut self) -> Result<Option<Vec<Vec<u8>>>, AppError> {
+        let Some(node) = self.nodes.last() else {
+            return Ok(None);
+        };
+        let mut scan = deadpool_redis::redis::cmd("SCAN");
+        scan.arg(self.cursor)
+            .arg("MATCH")
+            .arg(&self.pattern)
+            .arg("COUNT")
+            .arg(SCAN_BATCH);
+        let (next, keys): (u64, Vec<Vec<u8>>) = match (&mut self.con, node) {
+            (Connection::Cluster(cluster), Some((host, port))) => {
+                let routing = RoutingInfo::SingleNode(SingleNodeRoutingInfo::ByAddress {
+                    host: host.clone(),
+                    port: *port,
+                });
+                timed(self.timeout, cluster.route_command(&scan, routing))
+                    .await?
+                    .and_then(|reply| from_redis_value(&reply))?
+            }
+            _ => timed(self.timeout, scan.query_async(&mut self.con)).await??,
+        };
+        self.cursor = next;
+        if next == 0 {
+            self.nodes.pop();
+        }
+        Ok(Some(keys))
+    }
+}
+
+/// `UNLINK`s every key starting with `prefix` (see `PrefixScan`). Returns how
+/// many keys were removed.
+pub async fn unlink_prefix(pool: &Pool, prefix: &str, timeout: Duration) -> Result<u64, AppError> {
+    let mut scan = PrefixScan::new(pool, prefix, timeout).await?;
+    let mut removed = 0;
+    while let Some(keys) = scan.next_batch().await? {
+        // A cluster client splits a multi-key UNLINK by slot itself.
+        for batch in keys.chunks(SCAN_BATCH) {
+            let unlink = deadpool_redis::redis::cmd("UNLINK").arg(batch).clone();
+            removed += timed(timeout, unlink.query_async::<u64>(&mut scan.con)).await??;
+        }
+    }
+    Ok(removed)
+}
+
+/// Up to `limit` keys starting with `prefix` (see `PrefixScan`) with their
+/// values, in no particular order. Keys that expire mid-scan are skipped.
+pub async fn get_prefix(
+    pool: &Pool,
+    prefix: &str,
+    limit: usize,
+    timeout: Duration,
+) -> Result<Vec<(String, Vec<u8>)>, AppError> {
+    let mut scan = PrefixScan::new(pool, prefix, timeout).await?;
+    let mut values = Vec::new();
+    while values.len() < limit {
+        let Some(mut keys) = scan.next_batch().await? else {
+            break;
+        };
+        keys.truncate(limit - values.len());
+        if keys.is_empty() {
+            continue;
+        }
+        let mget = deadpool_redis::redis::cmd("MGET").arg(&keys).clone();
+        let batch: Vec<Option<Vec<u8>>> = timed(timeout, mget.query_async(&mut scan.con)).await??;
+        let found = keys
+            .into_iter()
+            .zip(batch)
+            .filter_map(|(key, value)| Some((String::from_utf8_lossy(&key).into_owned(), value?)));
+        values.extend(found);
+    }
+    Ok(values)
+}
+
+async fn timed<T>(timeout: Duration, call: impl Future<Output = T>) -> Result<T, AppError> {
+    tokio::time::timeout(timeout, call)
+        .await
+        .map_err(|_| AppError::StorageTimeout)
+}
+
+/// `host:port` of the primaries in a `CLUSTER NODES` reply.
+fn cluster_primaries(nodes: &str) -> Vec<(String, u16)> {
+    nodes
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let address = fields.nth(1)?;
+            let flags = fields.next()?;
+            if !flags.split(',').any(|flag| flag == "master")
+                || flags
+                    .split(',')
+                    .any(|flag| flag == "fail" || flag == "noaddr")
+            {
+                return None;
+            }
+            // `ip:port@cport[,hostname]`
+            let (host, port) = address.split(['@', ',']).next()?.rsplit_once(':')?;
+            Some((host.to_string(), port.parse().ok()?))
+        })
+        .filter(|(host, port)| !host.is_empty() && *port != 0)
+        .collect()
+}
+
+/// Makes `SCAN MATCH` take `prefix` literally.
+fn escape_glob(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn is_access_denied(err: &RedisError) -> bool {
+    err.kind() == ErrorKind::AuthenticationFailed
+        || matches!(err.code(), Some("WRONGPASS" | "NOAUTH" | "NOPERM"))
+}
+
+/// Gets a pooled connection, retrying up to `retries` more times with
+/// exponential backoff starting at `backoff`. Callers bound the total time
+/// with their own timeout.
+pub async fn connect(
+    pool: &Pool,
+    retries: u32,
+    backoff: Duration,
+) -> Result<Connection, PoolError> {
+    let mut delay = backoff;
+    let mut attempt = 0;
+    loop {
+        match pool.get().await {
+            Ok(con) => return Ok(con),
+            Err(err) if attempt < retries => {
+                tracing::debug!(error = ?err, attempt, "Redis connection failed, retrying");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_primaries() {
+        let nodes = "\
+07c37dfe 10.0.0.1:6379@16379 myself,master - 0 0 1 connected 0-5460
+67ed2db8 10.0.0.2:6379@16379,redis-2 master - 0 0 2 connected 5461-10922
+292f8b36 10.0.0.3:6379@16379 master,fail - 0 0 3 connected 10923-16383
+6ec23923 10.0.0.4:6380@16380 slave 07c37dfe 0 0 1 connected
+";
+        assert_eq!(
+            cluster_primaries(nodes),
+            vec![
+                ("10.0.0.1".to_string(), 6379),
+                ("10.0.0.2".to_string(), 6379)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escape_glob() {
+        assert_eq!(escape_glob("paste:{"), "paste:{");
+        assert_eq!(escape_glob("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\");
+    }
 }
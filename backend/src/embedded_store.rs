@@ -0,0 +1,531 @@
+use crate::config::EmbeddedStoreConfig;
+use crate::error::AppError;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zeroize::Zeroize;
+
+/// Rough per-entry bookkeeping (map slot, key allocation, timer) counted
+/// against the byte budget on top of key and value lengths.
+const ENTRY_OVERHEAD: u64 = 128;
+/// Timer wheel resolution. Reads check exact deadlines themselves; the wheel
+/// only decides when memory is reclaimed.
+const TICK_MS: u64 = 1000;
+const WHEEL_SLOTS: usize = 4096;
+
+/// In-process replacement for Redis, for single-binary deployments.
+///
+/// Keys live in a sharded map; expiry is tracked on a hashed timer wheel
+/// driven by `spawn_reaper`, plus a deadline check on every read. Writes
+/// that would take the store past its byte budget fail with `StorageFull`
+/// instead of evicting anything. Nothing is ever written to disk.
+pub struct EmbeddedStore {
+    shards: Box<[Mutex<HashMap<String, Entry>>]>,
+    hasher: RandomState,
+    wheel: Mutex<Wheel>,
+    budget: u64,
+    used: AtomicU64,
+    epoch: Instant,
+}
+
+struct Entry {
+    value: Vec<u8>,
+    /// Milliseconds since `epoch`.
+    expires_at: Option<u64>,
+    /// Tick of the timer that will next look at this entry, if any.
+    timer: Option<u64>,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+/// Timers for tick `t` sit in slot `t % WHEEL_SLOTS`, possibly alongside
+/// timers for later rounds.
+struct Wheel {
+    slots: Vec<Vec<(String, u64)>>,
+    /// Last tick processed.
+    cursor: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetCondition {
+    Always,
+    /// Like Redis `NX`.
+    IfAbsent,
+    /// Like Redis `XX`.
+    IfPresent,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Expiry {
+    /// Like Redis `KEEPTTL`; a new key gets no expiry.
+    Keep,
+    After(Duration),
+}
+
+impl EmbeddedStore {
+    pub fn new(config: &EmbeddedStoreConfig) -> io::Result<Arc<Self>> {
+        forbid_core_dumps()?;
+        if config.mlock {
+            lock_memory()?;
+        }
+        let shards = (0..config.shards.max(1))
+            .map(|_| Mutex::new(HashMap::new()))
+            .collect();
+        Ok(Arc::new(Self {
+            shards,
+            hasher: RandomState::new(),
+            wheel: Mutex::new(Wheel {
+                slots: vec![Vec::new(); WHEEL_SLOTS],
+                cursor: 0,
+            }),
+            budget: config.budget_bytes,
+            used: AtomicU64::new(0),
+            epoch: Instant::now(),
+        }))
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn budget_bytes(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let now = self.now();
+        let shard = self.shard(key).lock().unwrap();
+        shard
+            .get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Returns whether the value was stored; `false` means `condition` did
+    /// not hold.
+    pub fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        condition: SetCondition,
+        expiry: Expiry,
+    ) -> Result<bool, AppError> {
+        let now = self.now();
+        let mut shard = self.shard(key).lock().unwrap();
+        let existing = shard.get(key).filter(|entry| !entry.is_expired(now));
+        match (condition, existing.is_some()) {
+            (SetCondition::IfAbsent, true) | (SetCondition::IfPresent, false) => return Ok(false),
+            _ => {}
+        }
+
+        let old_cost = shard.get(key).map_or(0, |entry| cost(key, &entry.value));
+        self.reserve(cost(key, &value), old_cost)?;
+        let expires_at = match expiry {
+            Expiry::Keep => existing.and_then(|entry| entry.expires_at),
+            Expiry::After(ttl) => Some(now + ttl.as_millis() as u64),
+        };
+        let timer = shard.get(key).and_then(|entry| entry.timer);
+        shard.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires_at,
+                timer,
+            },
+        );
+        let schedule = self.arm(shard.get_mut(key).unwrap());
+        drop(shard);
+        self.schedule(key, schedule);
+        Ok(true)
+    }
+
+    /// Atomically replaces the value of `key` with what `update` makes of the
+    /// current one, and sets its expiry to `ttl`.
+    pub fn update<T>(
+        &self,
+        key: &str,
+        ttl: Duration,
+        update: impl FnOnce(Option<&[u8]>) -> (Vec<u8>, T),
+    ) -> Result<T, AppError> {
+        let now = self.now();
+        let mut shard = self.shard(key).lock().unwrap();
+        let current = shard
+            .get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.value.as_slice());
+        let (value, result) = update(current);
+
+        let old_cost = shard.get(key).map_or(0, |entry| cost(key, &entry.value));
+        self.reserve(cost(key, &value), old_cost)?;
+        let timer = shard.get(key).and_then(|entry| entry.timer);
+        shard.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires_at: Some(now + ttl.as_millis() as u64),
+                timer,
+            },
+        );
+        let schedule = self.arm(shard.get_mut(key).unwrap());
+        drop(shard);
+        self.schedule(key, schedule);
+        Ok(result)
+    }
+
+    /// Like Redis `EXPIRE`; returns whether the key exists.
+    pub fn expire(&self, key: &str, ttl: Duration) -> bool {
+        self.set_deadline(key, ttl, false)
+    }
+
+    /// Like Redis `EXPIRE ... LT`: only ever brings the deadline forward.
+    pub fn expire_sooner(&self, key: &str, ttl: Duration) -> bool {
+        self.set_deadline(key, ttl, true)
+    }
+
+    fn set_deadline(&self, key: &str, ttl: Duration, only_sooner: bool) -> bool {
+        let now = self.now();
+        let mut shard = self.shard(key).lock().unwrap();
+        let Some(entry) = shard.get_mut(key).filter(|entry| !entry.is_expired(now)) else {
+            return false;
+        };
+        let deadline = now + ttl.as_millis() as u64;
+        if only_sooner && entry.expires_at.is_some_and(|at| at <= deadline) {
+            return true;
+        }
+        entry.expires_at = Some(deadline);
+        let schedule = self.arm(entry);
+        drop(shard);
+        self.schedule(key, schedule);
+        true
+    }
+
+    pub fn delete(&self, key: &str) -> bool {
+        let now = self.now();
+        let removed = self.shard(key).lock().unwrap().remove(key);
+        match removed {
+            Some(entry) => {
+                self.release(cost(key, &entry.value));
+                !entry.is_expired(now)
+            }
+            None => false,
+        }
+    }
+
+    /// Removes every key starting with `prefix`; returns how many were live.
+    pub fn delete_prefix(&self, prefix: &str) -> u64 {
+        let now = self.now();
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().retain(|key, entry| {
+                if !key.starts_with(prefix) {
+                    return true;
+                }
+                self.release(cost(key, &entry.value));
+                if !entry.is_expired(now) {
+                    removed += 1;
+                }
+                false
+            });
+        }
+        removed
+    }
+
+    /// Up to `limit` live keys starting with `prefix` with their values, in
+    /// no particular order.
+    pub fn get_prefix(&self, prefix: &str, limit: usize) -> Vec<(String, Vec<u8>)> {
+        let now = self.now();
+        let mut values = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            let live = shard
+                .iter()
+                .filter(|(key, entry)| key.starts_with(prefix) && !entry.is_expired(now))
+                .map(|(key, entry)| (key.clone(), entry.value.clone()));
+            values.extend(live.take(limit - values.len()));
+            if values.len() == limit {
+                break;
+            }
+        }
+        values
+    }
+
+    /// Reclaims expired entries every tick until the store is dropped.
+    pub fn spawn_reaper(self: &Arc<Self>) {
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_millis(TICK_MS));
+            loop {
+                ticker.tick().await;
+                let Some(store) = store.upgrade() else {
+                    return;
+                };
+                store.reap(store.now());
+            }
+        });
+    }
+
+    fn reap(&self, now: u64) {
+        let now_tick = now / TICK_MS;
+        let due: Vec<(String, u64)> = {
+            let mut wheel = self.wheel.lock().unwrap();
+            // After a stall longer than one rotation, every slot is due once.
+            let first = wheel
+                .cursor
+                .saturating_add(1)
+                .max(now_tick.saturating_sub(WHEEL_SLOTS as u64 - 1));
+            let mut due = Vec::new();
+            for tick in first..=now_tick {
+                let slot = &mut wheel.slots[tick as usize % WHEEL_SLOTS];
+                let (ready, later) = std::mem::take(slot)
+                    .into_iter()
+                    .partition(|(_, at)| *at <= now_tick);
+                *slot = later;
+                due.extend::<Vec<_>>(ready);
+            }
+            wheel.cursor = wheel.cursor.max(now_tick);
+            due
+        };
+
+        for (key, tick) in due {
+            let mut shard = self.shard(&key).lock().unwrap();
+            let Some(entry) = shard.get_mut(&key) else {
+                continue;
+            };
+            // A stale timer; the entry was re-armed for another tick.
+            if entry.timer != Some(tick) {
+                continue;
+            }
+            entry.timer = None;
+            if entry.is_expired(now) {
+                let entry = shard.remove(&key).unwrap();
+                self.release(cost(&key, &entry.value));
+            } else {
+                // The deadline moved later since this timer was set.
+                let schedule = self.arm(entry);
+                drop(shard);
+                self.schedule(&key, schedule);
+            }
+        }
+    }
+
+    /// Makes sure a timer fires no later than the entry's deadline, and
+    /// returns the tick to schedule if a new timer is needed. An existing
+    /// earlier timer is kept; it re-arms the entry when it fires.
+    fn arm(&self, entry: &mut Entry) -> Option<u64> {
+        let tick = entry.expires_at? / TICK_MS;
+        match entry.timer {
+            Some(timer) if timer <= tick => None,
+            _ => {
+                entry.timer = Some(tick);
+                Some(tick)
+            }
+        }
+    }
+
+    fn schedule(&self, key: &str, tick: Option<u64>) {
+        if let Some(tick) = tick {
+            let mut wheel = self.wheel.lock().unwrap();
+            // Never behind the cursor, or the timer would wait a full round.
+            // The entry keeps matching it by its original tick.
+            let slot = tick.max(wheel.cursor + 1) as usize % WHEEL_SLOTS;
+            wheel.slots[slot].push((key.to_string(), tick));
+        }
+    }
+
+    fn reserve(&self, new_cost: u64, old_cost: u64) -> Result<(), AppError> {
+        if new_cost <= old_cost {
+            self.release(old_cost - new_cost);
+            return Ok(());
+        }
+        let extra = new_cost - old_cost;
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(extra)
+                    .filter(|total| *total <= self.budget)
+            })
+            .map(|_| ())
+            .map_err(|_| AppError::StorageFull)
+    }
+
+    fn release(&self, bytes: u64) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<HashMap<String, Entry>> {
+        &self.shards[self.hasher.hash_one(key) as usize % self.shards.len()]
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+}
+
+impl Entry {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+fn cost(key: &str, value: &[u8]) -> u64 {
+    (key.len() + value.len()) as u64 + ENTRY_OVERHEAD
+}
+
+/// A core dump would write every paste to disk.
+fn forbid_core_dumps() -> io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: plain syscall on a valid, initialised struct.
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Pins the whole process, and with it every stored paste, in RAM so nothing
+/// is ever paged out to swap. Needs `CAP_IPC_LOCK` or a large enough
+/// `RLIMIT_MEMLOCK` (e.g. `ulimits: memlock: -1` in compose).
+fn lock_memory() -> io::Result<()> {
+    // SAFETY: plain syscall without pointers.
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(budget_bytes: u64) -> Arc<EmbeddedStore> {
+        EmbeddedStore::new(&EmbeddedStoreConfig {
+            budget_bytes,
+            shards: 4,
+            mlock: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_set_conditions() {
+        let store = store(1 << 20);
+        let ttl = Expiry::After(Duration::from_secs(60));
+        assert!(!store
+            .set("a", b"1".to_vec(), SetCondition::IfPresent, ttl)
+            .unwrap());
+        assert!(store
+            .set("a", b"1".to_vec(), SetCondition::IfAbsent, ttl)
+            .unwrap());
+        assert!(!store
+            .set("a", b"2".to_vec(), SetCondition::IfAbsent, ttl)
+            .unwrap());
+        assert!(store
+            .set("a", b"3".to_vec(), SetCondition::IfPresent, Expiry::Keep)
+            .unwrap());
+        assert_eq!(store.get("a").unwrap(), b"3");
+        assert!(store.delete("a"));
+        assert!(store.get("a").is_none());
+        assert_eq!(store.used_bytes(), 0);
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        let store = store(1 << 20);
+        for key in ["paste:{a}", "paste:{b}", "acme:paste:{c}"] {
+            store
+                .set(key, b"x".to_vec(), SetCondition::Always, Expiry::Keep)
+                .unwrap();
+        }
+        assert_eq!(store.get_prefix("paste:{", 10).len(), 2);
+        assert_eq!(store.get_prefix("paste:{", 1).len(), 1);
+        assert_eq!(store.delete_prefix("paste:{"), 2);
+        assert!(store.get("acme:paste:{c}").is_some());
+        assert_eq!(store.used_bytes(), cost("acme:paste:{c}", b"x"));
+    }
+
+    #[test]
+    fn test_budget_is_enforced_without_eviction() {
+        let budget = 2 * cost("k0", &[0; 100]);
+        let store = store(budget);
+        let forever = Expiry::Keep;
+        for key in ["k0", "k1"] {
+            assert!(store
+                .set(key, vec![0; 100], SetCondition::Always, forever)
+                .unwrap());
+        }
+        assert!(matches!(
+            store.set("k2", vec![0; 100], SetCondition::Always, forever),
+            Err(AppError::StorageFull)
+        ));
+        // Nothing was dropped to make room, and shrinking still works.
+        assert!(store.get("k0").is_some() && store.get("k1").is_some());
+        assert!(store
+            .set("k0", vec![0; 10], SetCondition::Always, forever)
+            .unwrap());
+        assert_eq!(store.used_bytes(), budget - 90);
+    }
+
+    #[test]
+    fn test_expiry_and_reaping() {
+        let store = store(1 << 20);
+        store
+            .set(
+                "short",
+                b"x".to_vec(),
+                SetCondition::Always,
+                Expiry::After(Duration::from_millis(1500)),
+            )
+            .unwrap();
+        store
+            .set(
+                "long",
+                b"y".to_vec(),
+                SetCondition::Always,
+                Expiry::After(Duration::from_secs(3600)),
+            )
+            .unwrap();
+        // Extending a deadline keeps the earlier timer, which re-arms it.
+        assert!(store.expire("long", Duration::from_secs(7200)));
+        // ...but `expire_sooner` never pushes it back out.
+        assert!(store.expire_sooner("long", Duration::from_secs(10_800)));
+        let used = store.used_bytes();
+
+        store.reap(1000);
+        assert_eq!(store.used_bytes(), used);
+        store.reap(3000);
+        assert_eq!(store.used_bytes(), used - cost("short", b"x"));
+        assert!(store.shard("short").lock().unwrap().get("short").is_none());
+
+        // Past the original hour, but inside the extended two.
+        store.reap(3_601_000);
+        assert!(store.shard("long").lock().unwrap().contains_key("long"));
+        store.reap(7_201_000);
+        assert_eq!(store.used_bytes(), 0);
+    }
+
+    #[test]
+    fn test_update_is_read_modify_write() {
+        let store = store(1 << 20);
+        let ttl = Duration::from_secs(10);
+        let first = store
+            .update("n", ttl, |current| (b"1".to_vec(), current.is_none()))
+            .unwrap();
+        let second = store
+            .update("n", ttl, |current| {
+                (b"2".to_vec(), current.map(|value| value.to_vec()))
+            })
+            .unwrap();
+        assert!(first);
+        assert_eq!(second.unwrap(), b"1");
+        assert_eq!(store.get("n").unwrap(), b"2");
+    }
+}
@@ -0,0 +1,89 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use std::sync::Arc;
+
+const NONCE_LEN: usize = 24;
+
+/// Server-side encryption of stored paste records.
+///
+/// Paste bodies are already encrypted by the client, but the record around
+/// them (burn flag, password flag, expiry, view count, burn token hash) is
+/// not. With a cipher installed, `PasteRepository` stores each record as
+/// `nonce || XChaCha20-Poly1305(record)` with the paste ID as associated
+/// data, so Redis (or a dump of it) only ever sees opaque bytes, and a record
+/// cannot be moved to another ID. The key lives only in backend memory: a
+/// restart with a fresh key makes the whole store unreadable at once.
+#[derive(Clone)]
+pub struct RecordCipher {
+    aead: Arc<XChaCha20Poly1305>,
+}
+
+impl RecordCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            aead: Arc::new(XChaCha20Poly1305::new(key.into())),
+        }
+    }
+
+    /// A cipher with a fresh random key that nothing else knows.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self::new(&key)
+    }
+
+    pub fn seal(&self, id: &str, record: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .aead
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: record,
+                    aad: id.as_bytes(),
+                },
+            )
+            .expect("XChaCha20-Poly1305 encrypts any record that fits in memory");
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// `None` for records sealed under another key or another ID, or not
+    /// sealed at all.
+    pub fn open(&self, id: &str, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: id.as_bytes(),
+                },
+            )
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_are_bound_to_key_and_id() {
+        let cipher = RecordCipher::random();
+        let record = br#"{"id":"abc","burn_after_read":true}"#;
+        let sealed = cipher.seal("abc", record);
+        assert!(!sealed.windows(4).any(|window| window == b"burn"));
+        assert_eq!(cipher.open("abc", &sealed).unwrap(), record);
+
+        assert!(cipher.open("abd", &sealed).is_none());
+        assert!(RecordCipher::random().open("abc", &sealed).is_none());
+        assert!(cipher.open("abc", record).is_none());
+    }
+}
@@ -1,49 +1,282 @@
 use axum::{
-    http::StatusCode,
+    extract::rejection::JsonRejection,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde_json::json;
 
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+#[derive(Debug)]
 pub enum AppError {
+    RouteNotFound,
     PasteNotFound,
-    Unauthorized(String),
-    BadRequest(String),
-    Conflict(String),
-    TooManyRequests,
-    InternalServerError,
+    PasteExpired,
+    PasteIdConflict,
+    /// A burn-after-read paste is leased to another reader.
+    PasteReserved {
+        retry_after_secs: u64,
+    },
+    InvalidPayload(String),
+    InvalidJson(StatusCode, String),
+    PayloadTooLarge,
+    PowMissingHeader(&'static str),
+    PowInvalidTimestamp,
+    PowExpired,
+    PowReplayed,
+    PowInvalidSignature,
+    PowDifficultyNotMet,
+    InvalidBurnToken,
+    /// Missing or wrong `X-Access-Proof` on a paste that requires one.
+    InvalidAccessProof,
+    /// Too many wrong access proofs or burn tokens for this paste.
+    AccessLocked {
+        retry_after_secs: u64,
+    },
+    /// Missing or wrong `Authorization: Bearer` on an admin route.
+    AdminUnauthorized,
+    TooManyRequests {
+        retry_after_secs: u64,
+    },
+    /// The client did not finish sending its body in time.
+    RequestTimeout,
+    /// The whole request ran past its deadline on our side.
+    DeadlineExceeded,
+    /// A single Redis call did not complete in time.
+    StorageTimeout,
+    /// Redis is past its memory high-water mark.
+    StorageFull,
+    /// Redis is unreachable or the circuit breaker is open.
+    StorageUnavailable {
+        retry_after_secs: u64,
+    },
+    Internal(anyhow::Error),
+}
+
+impl AppError {
+    /// Stable machine-readable identifier. Clients switch on this, so never
+    /// rename an existing code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::RouteNotFound => "route_not_found",
+            AppError::PasteNotFound => "paste_not_found",
+            AppError::PasteExpired => "paste_expired",
+            AppError::PasteIdConflict => "paste_id_conflict",
+            AppError::PasteReserved { .. } => "paste_reserved",
+            AppError::InvalidPayload(_) => "invalid_payload",
+            AppError::InvalidJson(..) => "invalid_json",
+            AppError::PayloadTooLarge => "payload_too_large",
+            AppError::PowMissingHeader(_) => "pow_missing_header",
+            AppError::PowInvalidTimestamp => "pow_invalid_timestamp",
+            AppError::PowExpired => "pow_expired",
+            AppError::PowReplayed => "pow_replayed",
+            AppError::PowInvalidSignature => "pow_invalid_signature",
+            AppError::PowDifficultyNotMet => "pow_difficulty_not_met",
+            AppError::InvalidBurnToken => "invalid_burn_token",
+            AppError::InvalidAccessProof => "invalid_access_proof",
+            AppError::AccessLocked { .. } => "access_locked",
+            AppError::AdminUnauthorized => "admin_unauthorized",
+            AppError::TooManyRequests { .. } => "too_many_requests",
+            AppError::RequestTimeout => "request_timeout",
+            AppError::DeadlineExceeded => "deadline_exceeded",
+            AppError::StorageTimeout => "storage_timeout",
+            AppError::StorageFull => "storage_full",
+            AppError::StorageUnavailable { .. } => "storage_unavailable",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::RouteNotFound | AppError::PasteNotFound => StatusCode::NOT_FOUND,
+            AppError::PasteIdConflict | AppError::PasteReserved { .. } => StatusCode::CONFLICT,
+            AppError::PasteExpired
+            | AppError::InvalidPayload(_)
+            | AppError::PowMissingHeader(_)
+            | AppError::PowInvalidTimestamp => StatusCode::BAD_REQUEST,
+            AppError::InvalidJson(status, _) => *status,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::PowExpired
+            | AppError::PowReplayed
+            | AppError::PowInvalidSignature
+            | AppError::PowDifficultyNotMet
+            | AppError::InvalidBurnToken
+            | AppError::InvalidAccessProof
+            | AppError::AdminUnauthorized => StatusCode::UNAUTHORIZED,
+            AppError::TooManyRequests { .. } | AppError::AccessLocked { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            AppError::RequestTimeout => StatusCode::REQUEST_TIMEOUT,
+            AppError::DeadlineExceeded
+            | AppError::StorageTimeout
+            | AppError::StorageUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::StorageFull => StatusCode::INSUFFICIENT_STORAGE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            AppError::RouteNotFound => "No such API endpoint",
+            AppError::PasteNotFound => "Paste not found",
+            AppError::PasteExpired => "Paste already expired",
+            AppError::PasteIdConflict => "Paste ID already exists",
+            AppError::PasteReserved { .. } => "Paste is being read by someone else",
+            AppError::InvalidPayload(_) => "Invalid paste payload",
+            AppError::InvalidJson(..) => "Malformed request body",
+            AppError::PayloadTooLarge => "Payload too large",
+            AppError::PowMissingHeader(_) => "Missing proof-of-work header",
+            AppError::PowInvalidTimestamp => "Invalid proof-of-work timestamp",
+            AppError::PowExpired => "PoW challenge expired",
+            AppError::PowReplayed => "PoW salt already used",
+            AppError::PowInvalidSignature => "Invalid PoW signature",
+            AppError::PowDifficultyNotMet => "PoW difficulty not met",
+            AppError::InvalidBurnToken => "Invalid burn token",
+            AppError::InvalidAccessProof => "Missing or invalid access proof",
+            AppError::AccessLocked { .. } => "Too many failed attempts for this paste",
+            AppError::AdminUnauthorized => "Missing or invalid admin token",
+            AppError::TooManyRequests { .. } => "Server busy, please try again later",
+            AppError::RequestTimeout => "Request body not received in time",
+            AppError::DeadlineExceeded => "Request took too long to process",
+            AppError::StorageTimeout => "Storage did not respond in time",
+            AppError::StorageFull => "Storage is full, please try again later",
+            AppError::StorageUnavailable { .. } => "Storage temporarily unavailable",
+            AppError::Internal(_) => "Internal server error",
+        }
+    }
+
+    /// Errors that mean Redis itself is down or stalled, as opposed to Redis
+    /// answering with something we did not want.
+    pub fn is_storage_outage(&self) -> bool {
+        matches!(
+            self,
+            AppError::StorageTimeout | AppError::StorageUnavailable { .. }
+        )
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            AppError::InvalidPayload(msg) | AppError::InvalidJson(_, msg) => Some(msg.clone()),
+            AppError::PowMissingHeader(name) => Some(format!("Missing {} header", name)),
+            _ => None,
+        }
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::PasteNotFound => (StatusCode::NOT_FOUND, "Paste not found".to_string()),
-            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg),
-            AppError::TooManyRequests => (
-                StatusCode::TOO_MANY_REQUESTS,
-                "Server busy, please try again later".to_string(),
-            ),
-            AppError::InternalServerError => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal server error".to_string(),
-            ),
-        };
-
-        let body = Json(json!({
-            "error": error_message
-        }));
-
-        (status, body).into_response()
-    }
-}
-
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(_err: E) -> Self {
-        Self::InternalServerError
+        let status = self.status();
+
+        // The source is for operators only; clients get the generic problem body.
+        if let AppError::Internal(ref err) = self {
+            tracing::error!(error = ?err, "request failed");
+        }
+
+        let mut body = json!({
+            "type": format!("urn:ghostbin:error:{}", self.code()),
+            "title": self.title(),
+            "status": status.as_u16(),
+            "code": self.code(),
+        });
+        if let Some(detail) = self.detail() {
+            body["detail"] = json!(detail);
+        }
+
+        let mut response = (status, Json(body)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+        );
+        if let AppError::TooManyRequests { retry_after_secs }
+        | AppError::AccessLocked { retry_after_secs }
+        | AppError::PasteReserved { retry_after_secs }
+        | AppError::StorageUnavailable { retry_after_secs } = self
+        {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+
+        response
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return AppError::PayloadTooLarge;
+        }
+        AppError::InvalidJson(rejection.status(), rejection.body_text())
+    }
+}
+
+/// Retry hint for connection failures that have not (yet) tripped the breaker.
+const STORAGE_RETRY_AFTER_SECS: u64 = 1;
+
+impl From<deadpool_redis::PoolError> for AppError {
+    fn from(err: deadpool_redis::PoolError) -> Self {
+        tracing::warn!(error = ?err, "could not get a Redis connection");
+        AppError::StorageUnavailable {
+            retry_after_secs: STORAGE_RETRY_AFTER_SECS,
+        }
+    }
+}
+
+impl From<deadpool_redis::redis::RedisError> for AppError {
+    fn from(err: deadpool_redis::redis::RedisError) -> Self {
+        // Only reachable with `maxmemory-policy noeviction` when the memory
+        // guard's last sample was already stale.
+        if err.code() == Some("OOM") {
+            return AppError::StorageFull;
+        }
+        if err.is_io_error()
+            || err.is_connection_dropped()
+            || err.is_connection_refusal()
+            || err.is_timeout()
+        {
+            tracing::warn!(error = ?err, "lost connection to Redis");
+            return AppError::StorageUnavailable {
+                retry_after_secs: STORAGE_RETRY_AFTER_SECS,
+            };
+        }
+        AppError::Internal(err.into())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::Internal(err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_too_many_requests_sets_retry_after() {
+        let response = AppError::TooManyRequests {
+            retry_after_secs: 3,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            PROBLEM_CONTENT_TYPE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_error_hides_source() {
+        let response =
+            AppError::Internal(anyhow::anyhow!("redis://secret-host refused")).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("\"code\":\"internal_error\""));
+        assert!(!text.contains("secret-host"));
     }
 }
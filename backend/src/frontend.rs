@@ -0,0 +1,280 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderName, HeaderValue, Response},
+    middleware::{self, Next},
+    Router,
+};
+use std::path::PathBuf;
+use tower_http::services::{ServeDir, ServeFile};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+/// Same policy nginx sets for the SPA. `wasm-unsafe-eval` is needed for the
+/// argon2 WebAssembly module.
+pub const CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self' 'wasm-unsafe-eval'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; font-src 'self' data:; media-src 'self' data:;";
+
+/// Vite writes content-hashed bundles here, so they can be cached forever.
+const HASHED_ASSETS_PREFIX: &str = "/assets/";
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrontendSource {
+    /// A Vite `dist/` directory on disk.
+    Directory(PathBuf),
+    /// `dist/` compiled into the binary (`embed-frontend` feature).
+    #[cfg(feature = "embed-frontend")]
+    Embedded,
+}
+
+/// Router serving the SPA with `index.html` as the fallback for unknown paths,
+/// except under `/assets/`: a missing bundle is a 404, never an `index.html`
+/// that would then be cached as immutable under the bundle's name.
+pub fn router<S>(source: &FrontendSource) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let router = match source {
+        FrontendSource::Directory(dir) => {
+            let serve_dir = ServeDir::new(dir)
+                .precompressed_br()
+                .precompressed_gzip()
+                .fallback(ServeFile::new(dir.join("index.html")));
+            let assets = ServeDir::new(dir.join(HASHED_ASSETS_PREFIX.trim_matches('/')))
+                .precompressed_br()
+                .precompressed_gzip();
+            Router::new()
+                .nest_service(HASHED_ASSETS_PREFIX.trim_end_matches('/'), assets)
+                .fallback_service(serve_dir)
+        }
+        #[cfg(feature = "embed-frontend")]
+        FrontendSource::Embedded => Router::new().fallback(embedded::serve),
+    };
+
+    router
+        .layer(middleware::from_fn(cache_control))
+        .layer(static_header(
+            header::CONTENT_SECURITY_POLICY,
+            CONTENT_SECURITY_POLICY,
+        ))
+        .layer(static_header(header::X_FRAME_OPTIONS, "DENY"))
+}
+
+fn static_header(name: HeaderName, value: &'static str) -> SetResponseHeaderLayer<HeaderValue> {
+    SetResponseHeaderLayer::if_not_present(name, HeaderValue::from_static(value))
+}
+
+async fn cache_control(request: Request, next: Next) -> Response<Body> {
+    let hashed = request.uri().path().starts_with(HASHED_ASSETS_PREFIX);
+    let mut response = next.run(request).await;
+
+    // index.html (including SPA fallbacks, which never answer for
+    // `/assets/`) must be revalidated so a deploy is picked up; hashed
+    // bundles never change under the same name.
+    let value = if hashed && response.status().is_success() {
+        IMMUTABLE
+    } else {
+        "no-cache"
+    };
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+    response
+}
+
+#[cfg(feature = "embed-frontend")]
+mod embedded {
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode},
+    };
+    use include_dir::{include_dir, Dir, File};
+
+    static DIST: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/../dist");
+
+    pub async fn serve(request: Request) -> Response<Body> {
+        if request.method() != Method::GET && request.method() != Method::HEAD {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        let requested = request.uri().path().trim_start_matches('/');
+        let (path, file) = match lookup(requested) {
+            Some(file) => (requested, file),
+            None if request
+                .uri()
+                .path()
+                .starts_with(super::HASHED_ASSETS_PREFIX) =>
+            {
+                return status(StatusCode::NOT_FOUND)
+            }
+            None => match lookup("index.html") {
+                Some(file) => ("index.html", file),
+                None => return status(StatusCode::NOT_FOUND),
+            },
+        };
+
+        let compressed = [("br", "br"), ("gzip", "gz")]
+            .into_iter()
+            .filter_map(|(coding, ext)| Some((coding, lookup(&format!("{}.{}", path, ext))?)))
+            .collect::<Vec<_>>();
+        let codings: Vec<&'static str> = compressed.iter().map(|(coding, _)| *coding).collect();
+        let chosen = preferred_encoding(request.headers(), &codings)
+            .and_then(|coding| compressed.iter().find(|(c, _)| *c == coding));
+
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        let mut builder = Response::builder()
+            .header(header::CONTENT_TYPE, mime.as_ref())
+            .header(header::VARY, "accept-encoding");
+        let body = match chosen {
+            Some((coding, compressed)) => {
+                builder = builder.header(header::CONTENT_ENCODING, *coding);
+                compressed.contents()
+            }
+            None => file.contents(),
+        };
+
+        builder
+            .body(Body::from(body))
+            .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR))
+    }
+
+    fn lookup(path: &str) -> Option<&'static File<'static>> {
+        if path.is_empty() {
+            return None;
+        }
+        DIST.get_file(path)
+    }
+
+    fn status(status: StatusCode) -> Response<Body> {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+        response
+    }
+
+    /// Picks the best encoding the client accepts out of those available.
+    fn preferred_encoding(headers: &HeaderMap, available: &[&'static str]) -> Option<&'static str> {
+        let accepted: Vec<&str> = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .filter_map(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let coding = parts.next()?;
+                let refused = parts.any(|p| p.replace(' ', "") == "q=0");
+                (!refused).then_some(coding)
+            })
+            .collect();
+
+        available
+            .iter()
+            .copied()
+            .find(|coding| accepted.contains(coding))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_preferred_encoding() {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::ACCEPT_ENCODING,
+                HeaderValue::from_static("gzip, br;q=0"),
+            );
+            assert_eq!(preferred_encoding(&headers, &["br", "gzip"]), Some("gzip"));
+            assert_eq!(preferred_encoding(&HeaderMap::new(), &["br"]), None);
+        }
+
+        #[tokio::test]
+        async fn test_missing_asset_is_not_found() {
+            use tower::ServiceExt;
+
+            let app: axum::Router = super::super::router(&super::super::FrontendSource::Embedded);
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/assets/missing-00000000.js")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_directory_spa_fallback_and_caching() {
+        let dir = std::env::temp_dir().join(format!("ghostbin-dist-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("index.html"), "<html>spa</html>").unwrap();
+        std::fs::write(dir.join("assets/index-AbC123xy.js"), "js").unwrap();
+        std::fs::write(dir.join("assets/index-AbC123xy.js.br"), "br-js").unwrap();
+
+        let app: Router = router(&FrontendSource::Directory(dir.clone()));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/assets/index-AbC123xy.js")
+                    .header(header::ACCEPT_ENCODING, "br")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/p/some-paste-id")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        assert_eq!(
+            response.headers()[header::CONTENT_SECURITY_POLICY],
+            CONTENT_SECURITY_POLICY
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"<html>spa</html>");
+
+        // A bundle from another deploy must not be answered with index.html,
+        // let alone one cached for a year.
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/assets/index-Old456zz.js")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,27 +1,64 @@
+use crate::audit::AuditLog;
+use crate::config::Config;
 use crate::error::AppError;
-use crate::model::{CreatePasteRequest, CreatePasteResponse, Paste};
-use crate::repository::PasteRepository;
+use crate::memory::MemoryGuard;
+use crate::model::{CreatePasteRequest, CreatePasteResponse, Paste, Report, ReportRequest};
+use crate::rate_limit::{LimitedRoute, RateLimiter};
+use crate::repository::{PasteRepository, Secret};
+use crate::tenant::Tenant;
+use crate::uniform;
 use axum::{
-    extract::{Path, State},
+    extract::{rejection::JsonRejection, Path, State},
     http::{HeaderMap, StatusCode},
-    Json,
+    Extension, Json,
 };
 use constant_time_eq::constant_time_eq;
 use hmac::{Hmac, Mac};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::Semaphore;
 use uuid::Uuid;
 
+/// `repository` and `rate_limiter` serve hosts that match no tenant; the
+/// tenants' own are derived from them (see `Tenants`).
 #[derive(Clone)]
 pub struct AppState {
+    pub config: Arc<Config>,
     pub repository: PasteRepository,
-    pub hmac_secret: [u8; 32],
+    pub hmac_secret: HmacSecret,
     pub read_limiter: Arc<Semaphore>,
     pub challenge_limiter: Arc<Semaphore>,
+    pub rate_limiter: RateLimiter,
+    pub memory: MemoryGuard,
+    pub audit: AuditLog,
+}
+
+/// Key that signs PoW challenges. Shared by every clone of `AppState`, so a
+/// rotation invalidates all outstanding challenges at once.
+#[derive(Clone)]
+pub struct HmacSecret(Arc<RwLock<[u8; 32]>>);
+
+impl HmacSecret {
+    pub fn random() -> Self {
+        rand::random::<[u8; 32]>().into()
+    }
+
+    pub fn get(&self) -> [u8; 32] {
+        *self.0.read().unwrap()
+    }
+
+    pub fn rotate(&self) {
+        *self.0.write().unwrap() = rand::random();
+    }
+}
+
+impl From<[u8; 32]> for HmacSecret {
+    fn from(secret: [u8; 32]) -> Self {
+        Self(Arc::new(RwLock::new(secret)))
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -40,17 +77,27 @@ pub struct PasteMetadata {
     pub burn_after_read: bool,
     pub created_at: i64,
     pub expires_at: Option<i64>,
+    /// Reads need `X-Access-Proof`, derived from the password and `salt`.
+    pub access_proof: bool,
+    pub salt: Option<String>,
+    /// Hardened mode only: brings every body to `uniform::METADATA_BODY_BYTES`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub padding: String,
 }
 
 const POW_DIFFICULTY: usize = 4;
+const BUSY_RETRY_AFTER_SECS: u64 = 1;
 
 pub async fn get_challenge(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
 ) -> Result<Json<ChallengeResponse>, AppError> {
     let _permit = state
         .challenge_limiter
         .try_acquire()
-        .map_err(|_| AppError::TooManyRequests)?;
+        .map_err(|_| AppError::TooManyRequests {
+            retry_after_secs: BUSY_RETRY_AFTER_SECS,
+        })?;
 
     let mut rng = rand::thread_rng();
     let mut salt_bytes = [0u8; 16];
@@ -64,15 +111,7 @@ pub async fn get_challenge(
 
     let difficulty = POW_DIFFICULTY; // Leading zeros required (hex characters)
 
-    // Create signature: HMAC-SHA256(salt + difficulty + timestamp)
-    type HmacSha256 = Hmac<Sha256>;
-    let mut mac =
-        HmacSha256::new_from_slice(&state.hmac_secret).expect("HMAC can take key of any size");
-    mac.update(salt.as_bytes());
-    mac.update(difficulty.to_string().as_bytes());
-    mac.update(timestamp.to_string().as_bytes());
-    let result = mac.finalize();
-    let signature = hex::encode(result.into_bytes());
+    let signature = sign_challenge(&state, &tenant, &salt, difficulty, timestamp);
 
     Ok(Json(ChallengeResponse {
         salt,
@@ -82,58 +121,98 @@ pub async fn get_challenge(
     }))
 }
 
+/// HMAC-SHA256(salt + difficulty + timestamp + tenant), so a challenge
+/// issued for one tenant is worthless on another.
+fn sign_challenge(
+    state: &AppState,
+    tenant: &Tenant,
+    salt: &str,
+    difficulty: usize,
+    timestamp: u64,
+) -> String {
+    type HmacSha256 = Hmac<Sha256>;
+    let mut mac = HmacSha256::new_from_slice(&state.hmac_secret.get())
+        .expect("HMAC can take key of any size");
+    mac.update(salt.as_bytes());
+    mac.update(difficulty.to_string().as_bytes());
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(tenant.name.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
 pub async fn get_paste_metadata(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
     Path(id): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Json<PasteMetadata>, AppError> {
-    let paste = state.repository.get_paste(&id).await?;
+    let paste = tenant.repository.get_paste_for_read(&id).await?;
+    let paste = paste.filter(|paste| holds_read_token(paste, &headers));
 
-    match paste {
-        Some(paste) => Ok(Json(PasteMetadata {
+    let mut metadata = match paste {
+        Some(paste) => PasteMetadata {
             exists: true,
             has_password: paste.has_password,
             burn_after_read: paste.burn_after_read,
             created_at: paste.created_at,
             expires_at: paste.expires_at,
-        })),
-        None => Ok(Json(PasteMetadata {
+            access_proof: paste.access_hash.is_some(),
+            // Only needed, and only sent, to derive the proof.
+            salt: paste.access_hash.and(paste.salt),
+            padding: String::new(),
+        },
+        None => PasteMetadata {
             exists: false,
             has_password: false,
             burn_after_read: false,
             created_at: 0,
             expires_at: None,
-        })),
+            access_proof: false,
+            salt: None,
+            padding: String::new(),
+        },
+    };
+
+    if state.config.hardening.enabled {
+        // Same length hit or miss, so the body size gives nothing away either.
+        let unpadded = serde_json::to_vec(&metadata)?.len() + r#","padding":"""#.len();
+        metadata.padding = " ".repeat(uniform::METADATA_BODY_BYTES.saturating_sub(unpadded));
     }
+    Ok(Json(metadata))
 }
 
-async fn verify_proof_of_work(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+async fn verify_proof_of_work(
+    state: &AppState,
+    tenant: &Tenant,
+    headers: &HeaderMap,
+) -> Result<(), AppError> {
     let pow_salt = headers
         .get("X-PoW-Salt")
         .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing X-PoW-Salt header".to_string()))?;
+        .ok_or(AppError::PowMissingHeader("X-PoW-Salt"))?;
 
     let pow_nonce = headers
         .get("X-PoW-Nonce")
         .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing X-PoW-Nonce header".to_string()))?;
+        .ok_or(AppError::PowMissingHeader("X-PoW-Nonce"))?;
 
     let pow_ts_str = headers
         .get("X-PoW-Timestamp")
         .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing X-PoW-Timestamp header".to_string()))?;
+        .ok_or(AppError::PowMissingHeader("X-PoW-Timestamp"))?;
 
     let pow_sig = headers
         .get("X-PoW-Signature")
         .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing X-PoW-Signature header".to_string()))?;
+        .ok_or(AppError::PowMissingHeader("X-PoW-Signature"))?;
 
-    if !state.repository.mark_salt_used(pow_salt).await? {
-        return Err(AppError::Unauthorized("PoW salt already used".to_string()));
+    if !tenant.repository.mark_salt_used(pow_salt).await? {
+        return Err(AppError::PowReplayed);
     }
 
     let pow_ts: u64 = pow_ts_str
         .parse()
-        .map_err(|_| AppError::BadRequest("Invalid X-PoW-Timestamp".to_string()))?;
+        .map_err(|_| AppError::PowInvalidTimestamp)?;
 
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -142,21 +221,14 @@ async fn verify_proof_of_work(state: &AppState, headers: &HeaderMap) -> Result<(
 
     // Check expiration (2 minutes)
     if now.saturating_sub(pow_ts) > 120 {
-        return Err(AppError::Unauthorized("PoW challenge expired".to_string()));
+        return Err(AppError::PowExpired);
     }
 
     // Verify Signature
     let difficulty = POW_DIFFICULTY;
-    type HmacSha256 = Hmac<Sha256>;
-    let mut mac =
-        HmacSha256::new_from_slice(&state.hmac_secret).expect("HMAC can take key of any size");
-    mac.update(pow_salt.as_bytes());
-    mac.update(difficulty.to_string().as_bytes());
-    mac.update(pow_ts.to_string().as_bytes());
-
-    let expected_sig = hex::encode(mac.finalize().into_bytes());
+    let expected_sig = sign_challenge(state, tenant, pow_salt, difficulty, pow_ts);
     if !constant_time_eq(expected_sig.as_bytes(), pow_sig.as_bytes()) {
-        return Err(AppError::Unauthorized("Invalid PoW signature".to_string()));
+        return Err(AppError::PowInvalidSignature);
     }
 
     // Verify Work
@@ -166,20 +238,97 @@ async fn verify_proof_of_work(state: &AppState, headers: &HeaderMap) -> Result<(
     let hash = hex::encode(hasher.finalize());
 
     if !hash.starts_with(&"0".repeat(difficulty)) {
-        return Err(AppError::Unauthorized("PoW difficulty not met".to_string()));
+        return Err(AppError::PowDifficultyNotMet);
     }
 
     Ok(())
 }
 
+/// Whether the request may learn the paste exists: one stored with a read
+/// hash is missing to anyone without the matching `X-Read-Token`. Wrong
+/// tokens are not counted like access proofs are: the token carries the
+/// link key's entropy, and a lockout would itself confirm the paste exists.
+fn holds_read_token(paste: &Paste, headers: &HeaderMap) -> bool {
+    let Some(read_hash) = &paste.read_hash else {
+        return true;
+    };
+    let token = headers
+        .get("X-Read-Token")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    constant_time_eq(
+        hex::encode(hasher.finalize()).as_bytes(),
+        read_hash.as_bytes(),
+    )
+}
+
+/// Checks `presented` against `stored_hash` (hex SHA-256), counting guesses
+/// per paste so the secret cannot be brute-forced through the API. Every
+/// guess is counted before it is checked, so however many arrive at once, no
+/// more than `ACCESS_MAX_FAILURES` are ever checked in a row without a right
+/// one; past that the paste locks, or for access proofs with
+/// `ACCESS_DESTROY_ON_LOCKOUT` is deleted.
+async fn verify_secret(
+    state: &AppState,
+    tenant: &Tenant,
+    id: &str,
+    secret: Secret,
+    presented: &str,
+    stored_hash: &str,
+) -> Result<(), AppError> {
+    let policy = &state.config.access;
+    let attempts = tenant
+        .repository
+        .record_attempt(id, secret, policy.lockout)
+        .await?;
+    // Refused unchecked, so a locked paste does not confirm a right guess.
+    if attempts > policy.max_failures {
+        return Err(AppError::AccessLocked {
+            retry_after_secs: policy.lockout.as_secs(),
+        });
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(presented.as_bytes());
+    let provided_hash = hex::encode(hasher.finalize());
+
+    if constant_time_eq(provided_hash.as_bytes(), stored_hash.as_bytes()) {
+        tenant.repository.clear_failures(id, secret).await?;
+        return Ok(());
+    }
+
+    if attempts == policy.max_failures && secret == Secret::AccessProof && policy.destroy {
+        tenant.repository.delete_paste(id).await?;
+        tracing::info!("paste destroyed after too many wrong access proofs");
+        return Err(AppError::PasteNotFound);
+    }
+    Err(match secret {
+        Secret::AccessProof => AppError::InvalidAccessProof,
+        Secret::BurnToken => AppError::InvalidBurnToken,
+    })
+}
+
 pub async fn create_paste(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
     headers: HeaderMap,
-    Json(req): Json<CreatePasteRequest>,
+    payload: Result<Json<CreatePasteRequest>, JsonRejection>,
 ) -> Result<(StatusCode, Json<CreatePasteResponse>), AppError> {
-    verify_proof_of_work(&state, &headers).await?;
+    let Json(req) = payload?;
+
+    // Before the PoW check, so a refused client keeps its solution.
+    state.memory.check()?;
 
-    req.validate().map_err(AppError::BadRequest)?;
+    verify_proof_of_work(&state, &tenant, &headers).await?;
+
+    req.validate().map_err(AppError::InvalidPayload)?;
+    if state.config.access.require_read_token && req.read_hash.is_none() {
+        return Err(AppError::InvalidPayload(
+            "this server requires a read token".to_string(),
+        ));
+    }
 
     let id = Uuid::new_v4().to_string();
 
@@ -195,7 +344,11 @@ pub async fn create_paste(
         salt: req.salt,
         encrypted_key: req.encrypted_key,
         key_iv: req.key_iv,
-        burn_token_hash: req.burn_token_hash,
+        // Compared against lowercase `hex::encode` output; an uppercase hash
+        // would otherwise turn away every right answer.
+        burn_token_hash: req.burn_token_hash.map(|hash| hash.to_ascii_lowercase()),
+        access_hash: req.access_hash.map(|hash| hash.to_ascii_lowercase()),
+        read_hash: req.read_hash.map(|hash| hash.to_ascii_lowercase()),
     };
 
     // Calculate TTL
@@ -211,7 +364,7 @@ pub async fn create_paste(
                 Some((diff / 1000) as u64)
             } else {
                 // Already expired
-                return Err(AppError::BadRequest("Paste already expired".to_string()));
+                return Err(AppError::PasteExpired);
             }
         } else {
             None
@@ -220,49 +373,118 @@ pub async fn create_paste(
         None
     };
 
-    // Default 30 days if no TTL or 0 (Never)
-    let mut final_ttl = ttl_seconds.unwrap_or(30 * 24 * 60 * 60);
-
-    const MAX_TTL: u64 = 30 * 24 * 60 * 60;
-    if final_ttl > MAX_TTL {
-        final_ttl = MAX_TTL;
-    }
+    // Tenant default if no TTL or 0 (Never), capped at the tenant maximum
+    let final_ttl = ttl_seconds
+        .unwrap_or(tenant.paste_ttl.default.as_secs())
+        .min(tenant.paste_ttl.max.as_secs());
 
-    state.repository.save_paste(paste, final_ttl).await?;
+    let size = paste.data.len() as u64;
+    tenant.repository.save_paste(paste, final_ttl).await?;
+    state.memory.record_write(size);
 
     Ok((StatusCode::CREATED, Json(CreatePasteResponse { id })))
 }
 
 pub async fn get_paste(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
     Path(id): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Json<Paste>, AppError> {
     let _permit = state
         .read_limiter
         .try_acquire()
-        .map_err(|_| AppError::TooManyRequests)?;
-
-    let paste = state.repository.get_paste(&id).await?;
-    let paste = paste.ok_or(AppError::PasteNotFound)?;
+        .map_err(|_| AppError::TooManyRequests {
+            retry_after_secs: BUSY_RETRY_AFTER_SECS,
+        })?;
+
+    let paste = tenant.repository.get_paste_for_read(&id).await?;
+    let mut paste = paste
+        .filter(|paste| holds_read_token(paste, &headers))
+        .ok_or(AppError::PasteNotFound)?;
+
+    if let Some(access_hash) = paste.access_hash.take() {
+        let proof = headers
+            .get("X-Access-Proof")
+            .and_then(|v| v.to_str().ok())
+            .ok_or(AppError::InvalidAccessProof)?;
+        verify_secret(
+            &state,
+            &tenant,
+            &id,
+            Secret::AccessProof,
+            proof,
+            &access_hash,
+        )
+        .await?;
+        paste.access_hash = Some(access_hash);
+    }
 
-    if paste.burn_after_read && !paste.has_password {
-        // set panic ttl (90s) burn burn burn away
-        state.repository.set_burn_timeout(&id, 90).await?;
-        Ok(Json(paste))
+    let mut paste = if !paste.burn_after_read {
+        tenant.repository.increment_views(paste).await?
     } else {
-        // Increment views
-        let updated_paste = state.repository.increment_views(paste).await?;
-        Ok(Json(updated_paste))
-    }
+        // Phase one: reserve it for this reader until `ack_paste` burns it
+        // or the lease lapses (e.g. the reader got the password wrong).
+        let lease = state.config.burn.lease;
+        if !tenant.repository.take_lease(&id, lease).await? {
+            return Err(AppError::PasteReserved {
+                retry_after_secs: lease.as_secs(),
+            });
+        }
+        if paste.burn_token_hash.is_none() {
+            // Nothing to acknowledge with, so the lapse is the
+            // acknowledgement: the paste goes with the lease.
+            tenant.repository.expire_paste(&id, lease).await?;
+        }
+        paste
+    };
+    paste.access_hash = None;
+    paste.read_hash = None;
+    Ok(Json(paste))
+}
+
+/// Phase two of burn-after-read: the reader proves it decrypted the paste
+/// with the burn token from inside the ciphertext, and the paste is gone.
+pub async fn ack_paste(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, AppError> {
+    let paste = tenant.repository.get_paste(&id).await?;
+    let paste = paste
+        .filter(|paste| holds_read_token(paste, &headers))
+        .ok_or(AppError::PasteNotFound)?;
+    let stored_hash = match (paste.burn_after_read, paste.burn_token_hash) {
+        (true, Some(stored_hash)) => stored_hash,
+        _ => {
+            return Err(AppError::InvalidPayload(
+                "paste is not burn-after-read".to_string(),
+            ))
+        }
+    };
+
+    let token = headers
+        .get("X-Burn-Token")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    verify_secret(&state, &tenant, &id, Secret::BurnToken, token, &stored_hash).await?;
+
+    tenant.repository.delete_paste(&id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 pub async fn delete_paste(
     State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
     Path(id): Path<String>,
     headers: HeaderMap,
 ) -> Result<StatusCode, AppError> {
-    let paste = state.repository.get_paste(&id).await?;
-    let paste = paste.ok_or(AppError::PasteNotFound)?;
+    let paste = tenant.repository.get_paste(&id).await?;
+    let paste = paste
+        .filter(|paste| holds_read_token(paste, &headers))
+        .ok_or(AppError::PasteNotFound)?;
 
     if paste.burn_after_read {
         if let Some(stored_hash) = paste.burn_token_hash {
@@ -271,17 +493,67 @@ pub async fn delete_paste(
                 .and_then(|v| v.to_str().ok())
                 .unwrap_or("");
 
-            let mut hasher = Sha256::new();
-            hasher.update(token.as_bytes());
-            let provided_hash = hex::encode(hasher.finalize());
-
-            if !constant_time_eq(provided_hash.as_bytes(), stored_hash.as_bytes()) {
-                return Err(AppError::Unauthorized("Invalid burn token".to_string()));
-            }
+            verify_secret(&state, &tenant, &id, Secret::BurnToken, token, &stored_hash).await?;
         }
     }
 
-    state.repository.delete_paste(&id).await?;
+    tenant.repository.delete_paste(&id).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Flags a paste for moderators. Identical reports about one paste share a
+/// rate-limit bucket, so repeating one adds nothing to the queue.
+pub async fn report_paste(
+    State(state): State<AppState>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    payload: Result<Json<ReportRequest>, JsonRejection>,
+) -> Result<StatusCode, AppError> {
+    let Json(req) = payload?;
+
+    state.memory.check()?;
+
+    verify_proof_of_work(&state, &tenant, &headers).await?;
+
+    req.validate().map_err(AppError::InvalidPayload)?;
+    // Stored in the clear, a key would sit next to the very ciphertext it
+    // opens, readable by anyone with access to the store.
+    if req.key.is_some() && !tenant.repository.seals_records() {
+        return Err(AppError::InvalidPayload(
+            "this server does not accept decryption keys in reports".to_string(),
+        ));
+    }
+
+    let paste = tenant.repository.get_paste(&id).await?;
+    if !paste.is_some_and(|paste| holds_read_token(&paste, &headers)) {
+        return Err(AppError::PasteNotFound);
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&(&req.reason, &req.message, &req.key))?);
+    let digest = hex::encode(&hasher.finalize()[..16]);
+    tenant
+        .rate_limiter
+        .check(LimitedRoute::ReportRepeat, &format!("{}:{}", id, digest))
+        .await?;
+
+    let report = Report {
+        id: Uuid::new_v4().to_string(),
+        paste_id: id,
+        reason: req.reason,
+        message: req.message,
+        key: req.key,
+        reported_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64,
+    };
+    tenant
+        .repository
+        .save_report(&report, state.config.reports.ttl.as_secs())
+        .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
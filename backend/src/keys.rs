@@ -0,0 +1,151 @@
+//! Redis key names.
+//!
+//! In cluster mode a key's slot is hashed from the part inside `{...}` when
+//! there is one, so every key belonging to a paste carries the paste ID as
+//! its hash tag. Any key that has to be read or written together with a
+//! paste (in one `MULTI`, script or multi-key command) must be built here
+//! with the same tag, or the cluster rejects it with `CROSSSLOT`.
+
+use std::sync::Arc;
+
+/// A key namespace. Instances (via `REDIS_KEY_PREFIX`) and tenants get
+/// their own prefix, so several of them can share one Redis without seeing
+/// each other's pastes, PoW salts or rate-limit buckets.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Keys {
+    prefix: Arc<str>,
+}
+
+impl Keys {
+    /// `prefix` must not contain `{` or `}`; it would move the hash tag.
+    pub fn new(prefix: &str) -> Self {
+        debug_assert!(!prefix.contains(['{', '}']));
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// A namespace inside this one, e.g. `ghostbin:` + `acme:`.
+    pub fn nested(&self, prefix: &str) -> Self {
+        Self::new(&format!("{}{}", self.prefix, prefix))
+    }
+
+    pub fn paste(&self, id: &str) -> String {
+        format!("{}paste:{{{}}}", self.prefix, id)
+    }
+
+    pub fn pow_salt(&self, salt: &str) -> String {
+        format!("{}pow:salt:{{{}}}", self.prefix, salt)
+    }
+
+    /// Moderation blocklist entry for paste `id`, in the paste's slot.
+    pub fn blocked(&self, id: &str) -> String {
+        format!("{}blocked:{{{}}}", self.prefix, id)
+    }
+
+    /// Burn-after-read lease for paste `id`, held by its first reader.
+    pub fn lease(&self, id: &str) -> String {
+        format!("{}lease:{{{}}}", self.prefix, id)
+    }
+
+    /// Count of wrong `kind` secrets (e.g. `access`) sent for paste `id`.
+    pub fn failures(&self, id: &str, kind: &str) -> String {
+        format!("{}failures:{{{}}}:{}", self.prefix, id, kind)
+    }
+
+    /// Abuse report `report_id` about paste `id`, in the paste's slot.
+    pub fn report(&self, id: &str, report_id: &str) -> String {
+        format!("{}{}", self.reports_for(id), report_id)
+    }
+
+    /// Every report about paste `id` starts with this.
+    pub fn reports_for(&self, id: &str) -> String {
+        format!("{}report:{{{}}}:", self.prefix, id)
+    }
+
+    /// Every paste key in this namespace starts with this.
+    pub fn paste_prefix(&self) -> String {
+        format!("{}paste:{{", self.prefix)
+    }
+
+    /// Every PoW salt key in this namespace starts with this.
+    pub fn pow_salt_prefix(&self) -> String {
+        format!("{}pow:salt:{{", self.prefix)
+    }
+
+    /// Every failure counter in this namespace starts with this.
+    pub fn failures_prefix(&self) -> String {
+        format!("{}failures:{{", self.prefix)
+    }
+
+    /// Every report key in this namespace starts with this.
+    pub fn report_prefix(&self) -> String {
+        format!("{}report:{{", self.prefix)
+    }
+
+    pub fn rate_limit(&self, route: &str, client: &str) -> String {
+        format!("{}ratelimit:{{{}:{}}}", self.prefix, route, client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The part of a key the cluster hashes, per the Redis Cluster spec.
+    fn hash_tag(key: &str) -> &str {
+        key.find('{')
+            .and_then(|open| {
+                let rest = &key[open + 1..];
+                rest.find('}')
+                    .filter(|&close| close > 0)
+                    .map(|close| &rest[..close])
+            })
+            .unwrap_or(key)
+    }
+
+    #[test]
+    fn test_keys_are_tagged() {
+        let keys = Keys::default();
+        assert_eq!(keys.paste("abc123"), "paste:{abc123}");
+        assert_eq!(hash_tag(&keys.paste("abc123")), "abc123");
+        assert_eq!(hash_tag(&keys.blocked("abc123")), "abc123");
+        assert_eq!(hash_tag(&keys.report("abc123", "r1")), "abc123");
+        assert_eq!(hash_tag(&keys.failures("abc123", "access")), "abc123");
+        assert_eq!(hash_tag(&keys.lease("abc123")), "abc123");
+        assert_eq!(hash_tag(&keys.pow_salt("s4lt")), "s4lt");
+        assert_eq!(
+            hash_tag(&keys.rate_limit("create", "ip:192.0.2.1")),
+            "create:ip:192.0.2.1"
+        );
+    }
+
+    #[test]
+    fn test_namespaces_do_not_overlap() {
+        let instance = Keys::new("ghostbin:");
+        let tenant = instance.nested("acme:");
+        assert_eq!(tenant.paste("abc"), "ghostbin:acme:paste:{abc}");
+        assert_eq!(hash_tag(&tenant.paste("abc")), "abc");
+        assert_ne!(instance.paste("abc"), tenant.paste("abc"));
+        assert_ne!(Keys::default().paste("abc"), instance.paste("abc"));
+        // Wiping one namespace must not reach into the other.
+        assert!(tenant.paste("abc").starts_with(&tenant.paste_prefix()));
+        assert!(!tenant
+            .paste("abc")
+            .starts_with(&Keys::default().paste_prefix()));
+        assert!(tenant.pow_salt("s").starts_with(&tenant.pow_salt_prefix()));
+        assert!(tenant
+            .report("abc", "r1")
+            .starts_with(&tenant.reports_for("abc")));
+        assert!(tenant
+            .report("abc", "r1")
+            .starts_with(&tenant.report_prefix()));
+        assert!(!tenant
+            .report("abcd", "r1")
+            .starts_with(&tenant.reports_for("abc")));
+    }
+}
@@ -1,45 +1,130 @@
+pub mod admin;
+pub mod audit;
+pub mod breaker;
+pub mod client;
+pub mod config;
+pub mod cors;
 pub mod db;
+#[cfg(feature = "embedded-store")]
+pub mod embedded_store;
+pub mod envelope;
 pub mod error;
+pub mod frontend;
 pub mod handlers;
+pub mod keys;
+pub mod listener;
+pub mod logging;
+pub mod memory;
+pub mod metrics;
 pub mod model;
+pub mod proxy_protocol;
+pub mod rate_limit;
 pub mod repository;
+pub mod security_headers;
+pub mod server;
+pub mod tenant;
+pub mod timeout;
+pub mod tls;
+pub mod uniform;
 
 use axum::{
     extract::DefaultBodyLimit,
+    handler::Handler,
     http::Method,
-    routing::{get, post},
+    middleware,
+    routing::{any, on, MethodFilter},
     Router,
 };
+use error::AppError;
 use handlers::AppState;
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
 
-pub fn app(state: AppState) -> Router {
-    let frontend_url =
-        std::env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
-
-    let cors = CorsLayer::new()
-        .allow_origin(
-            frontend_url
-                .parse::<axum::http::HeaderValue>()
-                .expect("Invalid FRONTEND_URL header value"),
-        )
-        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
-        .allow_headers(Any);
+/// Largest request body accepted (1.5MB), leaving room for the 1MB content
+/// limit plus encryption and JSON overhead.
+pub const MAX_BODY_BYTES: usize = 1024 * 1024 + 512 * 1024;
+
+/// API router that remembers which methods it answers to, so CORS allows
+/// exactly those and nothing else.
+struct ApiRoutes {
+    router: Router<AppState>,
+    methods: Vec<Method>,
+}
+
+impl ApiRoutes {
+    fn new() -> Self {
+        Self {
+            router: Router::new(),
+            methods: Vec::new(),
+        }
+    }
+
+    fn route<H, T>(mut self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(method.clone()).expect("unsupported route method");
+        if !self.methods.contains(&method) {
+            self.methods.push(method);
+        }
+        self.router = self.router.route(path, on(filter, handler));
+        self
+    }
+}
 
-    Router::new()
-        .route("/api/v1/challenge", get(handlers::get_challenge))
-        .route("/api/v1/paste", post(handlers::create_paste))
+pub fn app(state: AppState) -> Router {
+    // Hardened mode: lookups take as long for missing pastes as for real ones.
+    let uniform = middleware::from_fn_with_state(state.config.clone(), uniform::pad);
+    let api = ApiRoutes::new()
+        .route(Method::GET, "/api/v1/challenge", handlers::get_challenge)
+        .route(Method::POST, "/api/v1/paste", handlers::create_paste)
         .route(
+            Method::GET,
             "/api/v1/paste/:id",
-            get(handlers::get_paste).delete(handlers::delete_paste),
+            handlers::get_paste.layer(uniform.clone()),
         )
+        .route(Method::DELETE, "/api/v1/paste/:id", handlers::delete_paste)
         .route(
+            Method::GET,
             "/api/v1/paste/:id/metadata",
-            get(handlers::get_paste_metadata),
+            handlers::get_paste_metadata.layer(uniform),
+        )
+        .route(
+            Method::POST,
+            "/api/v1/paste/:id/report",
+            handlers::report_paste,
         )
-        .layer(DefaultBodyLimit::max(1024 * 1024 + 512 * 1024)) // 1.5MB limit
+        .route(Method::POST, "/api/v1/paste/:id/ack", handlers::ack_paste);
+    let cors = cors::layer(api.methods);
+    let tenants = tenant::Tenants::new(&state);
+
+    let mut router = api
+        .router
+        .route_layer(middleware::from_fn(rate_limit::enforce))
+        // Keep unknown API paths out of the SPA fallback below.
+        .route("/api/*rest", any(|| async { AppError::RouteNotFound }));
+
+    // With ADMIN_ADDR set the admin API is served there instead.
+    if state.config.admin.token.is_some() && state.config.admin.bind.is_none() {
+        router = router.merge(admin::router(&state));
+    }
+
+    if let Some(source) = &state.config.frontend {
+        router = router.merge(frontend::router(source));
+    }
+
+    router
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+        .layer(middleware::from_fn_with_state(
+            state.config.clone(),
+            timeout::enforce,
+        ))
         .layer(cors)
-        .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn_with_state(tenants, tenant::resolve))
+        .layer(middleware::from_fn_with_state(
+            state.config.clone(),
+            security_headers::apply,
+        ))
+        .layer(logging::trace_layer())
+        .layer(logging::sensitive_headers_layer())
         .with_state(state)
 }
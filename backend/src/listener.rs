@@ -0,0 +1,227 @@
+use std::fs::{self, Permissions};
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::path::{Path, PathBuf};
+use tokio::net::{TcpListener, UnixListener};
+
+/// First file descriptor passed by systemd socket activation (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+pub const DEFAULT_UNIX_SOCKET_MODE: u32 = 0o660;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenerSpec {
+    Tcp(SocketAddr),
+    Unix {
+        path: PathBuf,
+        mode: u32,
+    },
+    /// Every socket inherited through `LISTEN_FDS`.
+    Systemd,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListenerConfig {
+    pub spec: ListenerSpec,
+    pub proxy_protocol: bool,
+    /// Never terminate TLS on this listener, even when TLS is configured.
+    pub plain: bool,
+}
+
+impl ListenerConfig {
+    /// Parses one entry of `BIND_ADDR`: `<addr>[;option...]` where `<addr>` is
+    /// `host:port`, `unix:/path` or `systemd`, and options are `proxy`,
+    /// `plain` and `mode=<octal>` (Unix sockets only).
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut parts = value.split(';').map(str::trim);
+        let addr = parts.next().unwrap_or_default();
+
+        let mut proxy_protocol = false;
+        let mut plain = false;
+        let mut mode = None;
+        for option in parts {
+            match option.split_once('=') {
+                None if option == "proxy" => proxy_protocol = true,
+                None if option == "plain" => plain = true,
+                Some(("mode", octal)) => {
+                    mode = Some(
+                        u32::from_str_radix(octal, 8)
+                            .ok()
+                            .filter(|m| *m <= 0o777)
+                            .ok_or_else(|| format!("invalid socket mode {:?}", octal))?,
+                    )
+                }
+                _ => return Err(format!("unknown listener option {:?}", option)),
+            }
+        }
+
+        let spec = if let Some(path) = addr.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("unix: listener needs a path".to_string());
+            }
+            ListenerSpec::Unix {
+                path: path.into(),
+                mode: mode.unwrap_or(DEFAULT_UNIX_SOCKET_MODE),
+            }
+        } else if mode.is_some() {
+            return Err("mode= only applies to unix: listeners".to_string());
+        } else if addr == "systemd" {
+            ListenerSpec::Systemd
+        } else {
+            ListenerSpec::Tcp(
+                addr.parse()
+                    .map_err(|_| format!("invalid listen address {:?}", addr))?,
+            )
+        };
+
+        Ok(Self {
+            spec,
+            proxy_protocol,
+            plain,
+        })
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Listener::Tcp(listener)
+    }
+}
+
+impl From<UnixListener> for Listener {
+    fn from(listener: UnixListener) -> Self {
+        Listener::Unix(listener)
+    }
+}
+
+impl ListenerSpec {
+    pub async fn bind(&self) -> io::Result<Vec<Listener>> {
+        match self {
+            ListenerSpec::Tcp(addr) => Ok(vec![TcpListener::bind(addr).await?.into()]),
+            ListenerSpec::Unix { path, mode } => {
+                // A socket left behind by a previous run would make bind fail.
+                if let Ok(meta) = fs::symlink_metadata(path) {
+                    if meta.file_type().is_socket() {
+                        fs::remove_file(path)?;
+                    }
+                }
+                Ok(vec![bind_unix(path, *mode)?.into()])
+            }
+            ListenerSpec::Systemd => systemd_listeners(),
+        }
+    }
+}
+
+/// Binds inside a fresh 0700 directory next to `path`, sets `mode`, then
+/// renames the socket into place, so it is never reachable with the looser
+/// permissions the umask gives it at bind time.
+fn bind_unix(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let staging = parent.join(format!(".ghostbin-{:08x}", rand::random::<u32>()));
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+
+    let staged = staging.join("s");
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, Permissions::from_mode(mode))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let cleaned = fs::remove_dir_all(&staging);
+    let listener = bound?;
+    cleaned?;
+    Ok(listener)
+}
+
+fn systemd_listeners() -> io::Result<Vec<Listener>> {
+    let pid_matches = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let count: RawFd = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+
+    if !pid_matches || count <= 0 {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            "no sockets passed via LISTEN_FDS",
+        ));
+    }
+
+    // Children must not think these sockets are meant for them.
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(inherited_listener)
+        .collect()
+}
+
+fn inherited_listener(fd: RawFd) -> io::Result<Listener> {
+    // SAFETY: systemd hands us ownership of fds LISTEN_FDS_START.. and nothing
+    // else in this process touches them.
+    let tcp = unsafe { StdTcpListener::from_raw_fd(fd) };
+    if tcp.local_addr().is_ok() {
+        tcp.set_nonblocking(true)?;
+        return Ok(TcpListener::from_std(tcp)?.into());
+    }
+
+    // SAFETY: same fd, ownership moved out of the TcpListener above.
+    let unix = unsafe { StdUnixListener::from_raw_fd(tcp.into_raw_fd()) };
+    unix.local_addr()?;
+    unix.set_nonblocking(true)?;
+    Ok(UnixListener::from_std(unix)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listener_specs() {
+        assert_eq!(
+            ListenerConfig::parse("0.0.0.0:8080").unwrap(),
+            ListenerConfig {
+                spec: ListenerSpec::Tcp("0.0.0.0:8080".parse().unwrap()),
+                proxy_protocol: false,
+                plain: false,
+            }
+        );
+        assert_eq!(
+            ListenerConfig::parse("unix:/run/ghostbin/api.sock;proxy;mode=0600").unwrap(),
+            ListenerConfig {
+                spec: ListenerSpec::Unix {
+                    path: "/run/ghostbin/api.sock".into(),
+                    mode: 0o600,
+                },
+                proxy_protocol: true,
+                plain: false,
+            }
+        );
+        assert_eq!(
+            ListenerConfig::parse("systemd;plain").unwrap().spec,
+            ListenerSpec::Systemd
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_specs() {
+        assert!(ListenerConfig::parse("localhost").is_err());
+        assert!(ListenerConfig::parse("unix:").is_err());
+        assert!(ListenerConfig::parse("0.0.0.0:80;mode=0600").is_err());
+        assert!(ListenerConfig::parse("unix:/tmp/x.sock;mode=999").is_err());
+        assert!(ListenerConfig::parse("0.0.0.0:80;bogus").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{
+    extract::MatchedPath,
+    http::{header, HeaderName, Request, Response},
+};
+use std::time::Duration;
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::sensitive_headers::SetSensitiveRequestHeadersLayer;
+use tower_http::trace::{MakeSpan, OnResponse, TraceLayer};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+/// Headers whose values must never appear in logs. Marking them sensitive
+/// makes `HeaderValue`'s `Debug` impl print `Sensitive` instead of the value,
+/// so this also covers headers dumped by code outside this module.
+pub const SENSITIVE_HEADERS: [HeaderName; 9] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    HeaderName::from_static("x-burn-token"),
+    HeaderName::from_static("x-access-proof"),
+    HeaderName::from_static("x-read-token"),
+    HeaderName::from_static("x-pow-salt"),
+    HeaderName::from_static("x-pow-nonce"),
+    HeaderName::from_static("x-pow-timestamp"),
+    HeaderName::from_static("x-pow-signature"),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn init(format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env());
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+/// Request spans carry only the method and route template (`/api/v1/paste/:id`),
+/// never the URI, query string or headers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RedactedMakeSpan;
+
+impl<B> MakeSpan<B> for RedactedMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(MatchedPath::as_str)
+            .unwrap_or("<unmatched>");
+        tracing::info_span!("request", method = %request.method(), route = route)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StatusAndLatency;
+
+impl<B> OnResponse<B> for StatusAndLatency {
+    fn on_response(self, response: &Response<B>, latency: Duration, _span: &Span) {
+        tracing::info!(
+            status = response.status().as_u16(),
+            latency_ms = latency.as_millis() as u64,
+            "request completed"
+        );
+    }
+}
+
+pub fn trace_layer(
+) -> TraceLayer<SharedClassifier<ServerErrorsAsFailures>, RedactedMakeSpan, (), StatusAndLatency> {
+    TraceLayer::new_for_http()
+        .make_span_with(RedactedMakeSpan)
+        .on_request(())
+        .on_response(StatusAndLatency)
+}
+
+pub fn sensitive_headers_layer() -> SetSensitiveRequestHeadersLayer {
+    SetSensitiveRequestHeadersLayer::new(SENSITIVE_HEADERS)
+}
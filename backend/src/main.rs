@@ -1,9 +1,27 @@
 use dotenvy::dotenv;
-use ghostbin_server::{app, db, handlers::AppState, repository::PasteRepository};
-use rand::Rng;
-use std::net::SocketAddr;
+#[cfg(feature = "embedded-store")]
+use ghostbin_server::embedded_store::EmbeddedStore;
+use ghostbin_server::{
+    admin, app,
+    audit::AuditLog,
+    breaker::CircuitBreaker,
+    config::{Config, StoreConfig},
+    db,
+    envelope::RecordCipher,
+    handlers::{AppState, HmacSecret},
+    keys::Keys,
+    listener::{Listener, ListenerSpec},
+    logging,
+    memory::MemoryGuard,
+    metrics,
+    rate_limit::RateLimiter,
+    repository::PasteRepository,
+    server::{self, ServeOptions},
+    tls::{self, TlsAcceptor},
+};
 use std::sync::Arc;
 use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
 const MAX_CONCURRENT_READS: usize = 50;
 const MAX_CONCURRENT_CHALLENGES: usize = 100;
@@ -11,33 +29,255 @@ const MAX_CONCURRENT_CHALLENGES: usize = 100;
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    tracing_subscriber::fmt::init();
 
-    let pool = db::create_pool().expect("Failed to create Redis pool");
-    let repository = PasteRepository::new(pool);
+    // `ghostbin-server wipe` destroys every paste in Redis and exits.
+    let wipe_only = match std::env::args().nth(1).as_deref() {
+        None => false,
+        Some("wipe") => true,
+        Some(other) => {
+            eprintln!(
+                "unknown command {:?} (usage: ghostbin-server [wipe])",
+                other
+            );
+            std::process::exit(2);
+        }
+    };
+
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    logging::init(config.log_format);
+    if wipe_only && !matches!(config.store, StoreConfig::Redis) {
+        eprintln!(
+            "the embedded store lives inside the server process; \
+             use WIPE_SIGNAL or POST /api/v1/admin/wipe instead"
+        );
+        std::process::exit(1);
+    }
+
+    let memory = MemoryGuard::new(&config.memory);
+    let (repository, rate_limiter) = match &config.store {
+        StoreConfig::Redis => {
+            let pool = match db::create_pool(&config.redis) {
+                Ok(pool) => pool,
+                Err(err) => {
+                    tracing::error!(error = ?err, "failed to create Redis pool");
+                    std::process::exit(1);
+                }
+            };
+            let keys = Keys::new(&config.redis.key_prefix);
+            if let Err(err) = db::check_access(&pool, &keys, config.timeouts.redis_command).await {
+                tracing::error!(error = %err, "Redis startup check failed");
+                std::process::exit(1);
+            }
+            let breaker = CircuitBreaker::new(&config.redis);
+            let rate_limiter = RateLimiter::new(pool.clone(), &config.rate_limit)
+                .with_command_timeout(config.timeouts.redis_command)
+                .with_breaker(breaker.clone())
+                .with_keys(keys.clone());
+            let repository = PasteRepository::new(pool.clone())
+                .with_command_timeout(config.timeouts.redis_command)
+                .with_breaker(breaker)
+                .with_connect_retry(config.redis.connect_retries, config.redis.retry_backoff)
+                .with_keys(keys);
 
-    let mut rng = rand::thread_rng();
-    let hmac_secret: [u8; 32] = rng.gen();
+            memory.spawn_poller(
+                pool,
+                config.memory.poll_interval,
+                config.timeouts.redis_command,
+            );
+            (repository, rate_limiter)
+        }
+        #[cfg(feature = "embedded-store")]
+        StoreConfig::Embedded(store_config) => {
+            let store = match EmbeddedStore::new(store_config) {
+                Ok(store) => store,
+                Err(err) => {
+                    tracing::error!(error = %err, "failed to set up the embedded store");
+                    std::process::exit(1);
+                }
+            };
+            store.spawn_reaper();
+            memory.spawn_store_sampler(store.clone(), config.memory.poll_interval);
+            (
+                PasteRepository::embedded(store.clone()),
+                RateLimiter::embedded(store, &config.rate_limit),
+            )
+        }
+    };
+
+    let repository = if config.encryption.enabled {
+        let cipher = match &config.encryption.key {
+            Some(key) => RecordCipher::new(key),
+            None => RecordCipher::random(),
+        };
+        repository.with_cipher(cipher)
+    } else {
+        repository
+    };
 
     let read_limiter = Arc::new(Semaphore::new(MAX_CONCURRENT_READS));
     let challenge_limiter = Arc::new(Semaphore::new(MAX_CONCURRENT_CHALLENGES));
 
+    let audit = match AuditLog::open(&config.admin) {
+        Ok(audit) => audit,
+        Err(err) => {
+            tracing::error!(error = %err, "failed to open the audit log");
+            std::process::exit(1);
+        }
+    };
+
     let state = AppState {
+        config: Arc::new(config.clone()),
         repository,
-        hmac_secret,
+        hmac_secret: HmacSecret::random(),
         read_limiter,
         challenge_limiter,
+        rate_limiter,
+        memory,
+        audit,
     };
 
-    let app = app(state);
+    if wipe_only {
+        match admin::wipe_external(&state, "cli").await {
+            Ok(report) => {
+                println!(
+                    "destroyed {} pastes and {} reports",
+                    report.pastes, report.reports
+                );
+                // Running servers keep their PoW secret, so the replay
+                // markers for it stay until they expire.
+                println!("PoW salts were kept; use WIPE_SIGNAL to wipe those too");
+                return;
+            }
+            Err(err) => {
+                eprintln!("wipe failed: {:?}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(kind) = config.admin.wipe_signal {
+        admin::wipe_on_signal(state.clone(), kind).expect("failed to install wipe signal handler");
+    }
+
+    let app = app(state.clone());
+
+    let tls = config.server.tls.as_ref().map(|tls_config| {
+        let acceptor =
+            TlsAcceptor::from_config(tls_config).expect("failed to load TLS certificate");
+        tls::reload_on_sighup(acceptor.clone()).expect("failed to install SIGHUP handler");
+        acceptor
+    });
+
+    let mut servers = JoinSet::new();
+    let mut https_port = None;
+    for listener_config in &config.server.listeners {
+        let listeners = listener_config
+            .spec
+            .bind()
+            .await
+            .unwrap_or_else(|err| panic!("failed to bind {:?}: {}", listener_config.spec, err));
+
+        for listener in listeners {
+            let tls = match &listener {
+                Listener::Tcp(_) if !listener_config.plain => tls.clone(),
+                _ => None,
+            };
+            let description = match (&listener, &listener_config.spec) {
+                (Listener::Tcp(l), _) => {
+                    format!("{}", l.local_addr().expect("bound TCP listener"))
+                }
+                // Bound under a staging name and renamed; see `ListenerSpec::bind`.
+                (Listener::Unix(_), ListenerSpec::Unix { path, .. }) => {
+                    format!("unix:{}", path.display())
+                }
+                (Listener::Unix(l), _) => l
+                    .local_addr()
+                    .ok()
+                    .and_then(|addr| addr.as_pathname().map(|p| format!("unix:{}", p.display())))
+                    .unwrap_or_else(|| "unix socket".to_string()),
+            };
+            if let (Listener::Tcp(l), Some(_)) = (&listener, &tls) {
+                https_port.get_or_insert(l.local_addr().expect("bound TCP listener").port());
+            }
+            println!(
+                "Listening on {} ({}{})",
+                description,
+                if tls.is_some() { "https" } else { "http" },
+                if listener_config.proxy_protocol {
+                    ", PROXY protocol"
+                } else {
+                    ""
+                }
+            );
+
+            let options = ServeOptions {
+                proxy_protocol: listener_config.proxy_protocol,
+                tls,
+                header_read_timeout: Some(config.timeouts.header_read),
+                trusted_proxies: config.server.trusted_proxies.clone().into(),
+            };
+            servers.spawn(server::serve(listener, app.clone(), options));
+        }
+    }
+
+    if let Some(redirect_addr) = config.server.tls.as_ref().and_then(|t| t.redirect_from) {
+        let listener = tokio::net::TcpListener::bind(redirect_addr)
+            .await
+            .expect("failed to bind HTTP redirect listener");
+        println!("Redirecting HTTP on {} to HTTPS", redirect_addr);
+        let redirect = tls::redirect_app(https_port.unwrap_or(443));
+        servers.spawn(server::serve(
+            listener.into(),
+            redirect,
+            ServeOptions {
+                header_read_timeout: Some(config.timeouts.header_read),
+                ..Default::default()
+            },
+        ));
+    }
+
+    if let Some(admin_addr) = config.admin.bind {
+        let listener = tokio::net::TcpListener::bind(admin_addr)
+            .await
+            .expect("failed to bind admin listener");
+        println!("Serving the admin API on {}", admin_addr);
+        let router = admin::router(&state)
+            .with_state(state.clone())
+            .layer(logging::trace_layer())
+            .layer(logging::sensitive_headers_layer());
+        servers.spawn(server::serve(
+            listener.into(),
+            router,
+            ServeOptions {
+                header_read_timeout: Some(config.timeouts.header_read),
+                ..Default::default()
+            },
+        ));
+    }
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
-    println!("Listening on {}", addr);
+    if let Some(metrics_addr) = config.server.metrics_addr {
+        let listener = tokio::net::TcpListener::bind(metrics_addr)
+            .await
+            .expect("failed to bind metrics listener");
+        println!("Serving metrics on {}", metrics_addr);
+        servers.spawn(server::serve(
+            listener.into(),
+            metrics::router(state),
+            ServeOptions {
+                header_read_timeout: Some(config.timeouts.header_read),
+                ..Default::default()
+            },
+        ));
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .expect("failed to bind listener — is port 8080 in use?");
-    axum::serve(listener, app)
-        .await
-        .expect("server terminated unexpectedly");
+    if let Some(result) = servers.join_next().await {
+        result
+            .expect("server task panicked")
+            .expect("server terminated unexpectedly");
+    }
 }
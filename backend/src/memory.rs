@@ -0,0 +1,209 @@
+use crate::config::MemoryConfig;
+use crate::db::Pool;
+#[cfg(feature = "embedded-store")]
+use crate::embedded_store::EmbeddedStore;
+use crate::error::AppError;
+use deadpool_redis::redis::{FromRedisValue, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Refuses new pastes once Redis memory passes a high-water mark, so Redis
+/// never has to evict (or, with `noeviction`, reject writes for) live pastes.
+///
+/// Usage comes from `INFO memory`, sampled every `poll_interval`; bytes
+/// written since the last sample are added on top so a burst of uploads
+/// between polls is still counted. Until the first sample arrives, or when
+/// Redis has no `maxmemory` and no `REDIS_MEMORY_LIMIT_BYTES` is set, every
+/// paste is admitted.
+#[derive(Clone)]
+pub struct MemoryGuard {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    high_water_ratio: f64,
+    limit_override: Option<u64>,
+    used: AtomicU64,
+    /// 0 means "unknown", matching Redis' own `maxmemory:0`.
+    limit: AtomicU64,
+    written_since_sample: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl MemoryGuard {
+    pub fn new(config: &MemoryConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                high_water_ratio: config.high_water_ratio,
+                limit_override: config.limit_bytes,
+                used: AtomicU64::new(0),
+                limit: AtomicU64::new(config.limit_bytes.unwrap_or(0)),
+                written_since_sample: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn record_sample(&self, used: u64, maxmemory: u64) {
+        let limit = self.inner.limit_override.unwrap_or(maxmemory);
+        self.inner.used.store(used, Ordering::Relaxed);
+        self.inner.limit.store(limit, Ordering::Relaxed);
+        self.inner.written_since_sample.store(0, Ordering::Relaxed);
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.inner.used.load(Ordering::Relaxed)
+            + self.inner.written_since_sample.load(Ordering::Relaxed)
+    }
+
+    pub fn limit_bytes(&self) -> Option<u64> {
+        match self.inner.limit.load(Ordering::Relaxed) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn high_water_bytes(&self) -> Option<u64> {
+        self.limit_bytes()
+            .map(|limit| (limit as f64 * self.inner.high_water_ratio) as u64)
+    }
+
+    /// Bytes left before new pastes are refused.
+    pub fn headroom_bytes(&self) -> Option<u64> {
+        self.high_water_bytes()
+            .map(|mark| mark.saturating_sub(self.used_bytes()))
+    }
+
+    pub fn rejected_total(&self) -> u64 {
+        self.inner.rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.headroom_bytes() == Some(0) {
+            self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(AppError::StorageFull);
+        }
+        Ok(())
+    }
+
+    pub fn record_write(&self, bytes: u64) {
+        self.inner
+            .written_since_sample
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn spawn_poller(&self, pool: Pool, interval: Duration, command_timeout: Duration) {
+        let guard = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match tokio::time::timeout(command_timeout, sample(&pool)).await {
+                    Ok(Ok((used, maxmemory))) => guard.record_sample(used, maxmemory),
+                    Ok(Err(err)) => tracing::warn!(error = ?err, "failed to sample Redis memory"),
+                    Err(_) => tracing::warn!("timed out sampling Redis memory"),
+                }
+            }
+        });
+    }
+
+    /// The embedded store's counterpart of `spawn_poller`; its byte budget
+    /// plays the part of `maxmemory`.
+    #[cfg(feature = "embedded-store")]
+    pub fn spawn_store_sampler(&self, store: Arc<EmbeddedStore>, interval: Duration) {
+        let guard = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                guard.record_sample(store.used_bytes(), store.budget_bytes());
+            }
+        });
+    }
+}
+
+async fn sample(pool: &Pool) -> Result<(u64, u64), AppError> {
+    let mut con = pool.get().await?;
+    let reply: Value = deadpool_redis::redis::cmd("INFO")
+        .arg("memory")
+        .query_async(&mut con)
+        .await?;
+    let missing = || AppError::Internal(anyhow::anyhow!("INFO memory without used_memory"));
+    match reply {
+        // A cluster answers with one reply per primary. Pastes land on
+        // whichever shard owns their ID, so the fullest shard decides.
+        Value::Map(nodes) => nodes
+            .iter()
+            .map(|(_, info)| {
+                String::from_redis_value(info)
+                    .ok()
+                    .as_deref()
+                    .and_then(parse_info)
+            })
+            .collect::<Option<Vec<_>>>()
+            .and_then(|samples| fullest(&samples))
+            .ok_or_else(missing),
+        reply => parse_info(&String::from_redis_value(&reply)?).ok_or_else(missing),
+    }
+}
+
+/// The sample closest to its own `maxmemory`. Shards without a limit are
+/// only considered when no shard has one.
+fn fullest(samples: &[(u64, u64)]) -> Option<(u64, u64)> {
+    let limited = samples.iter().filter(|(_, maxmemory)| *maxmemory > 0);
+    limited
+        .max_by(|(a_used, a_max), (b_used, b_max)| {
+            (*a_used as f64 / *a_max as f64).total_cmp(&(*b_used as f64 / *b_max as f64))
+        })
+        .or_else(|| samples.iter().max_by_key(|(used, _)| *used))
+        .copied()
+}
+
+/// Extracts `used_memory` and `maxmemory` from an `INFO memory` reply.
+fn parse_info(info: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        info.lines()
+            .filter_map(|line| line.trim_end().split_once(':'))
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| value.parse::<u64>().ok())
+    };
+    Some((field("used_memory")?, field("maxmemory").unwrap_or(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let info = "# Memory\r\nused_memory:1048576\r\nused_memory_human:1.00M\r\nmaxmemory:536870912\r\nmaxmemory_policy:noeviction\r\n";
+        assert_eq!(parse_info(info), Some((1048576, 536870912)));
+        assert_eq!(parse_info("# Memory\r\n"), None);
+    }
+
+    #[test]
+    fn test_high_water_mark() {
+        let guard = MemoryGuard::new(&MemoryConfig::default());
+        assert!(guard.check().is_ok());
+
+        guard.record_sample(700, 1000);
+        assert_eq!(guard.headroom_bytes(), Some(150));
+        guard.record_write(200);
+        assert!(matches!(guard.check(), Err(AppError::StorageFull)));
+        assert_eq!(guard.rejected_total(), 1);
+
+        guard.record_sample(100, 1000);
+        assert!(guard.check().is_ok());
+    }
+
+    #[test]
+    fn test_fullest_shard() {
+        assert_eq!(
+            fullest(&[(300, 1000), (500, 2000), (50, 0)]),
+            Some((300, 1000))
+        );
+        assert_eq!(fullest(&[(300, 0), (600, 0)]), Some((600, 0)));
+        assert_eq!(fullest(&[]), None);
+    }
+}
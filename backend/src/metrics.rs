@@ -0,0 +1,68 @@
+use crate::handlers::AppState;
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use std::fmt::Write;
+
+/// Prometheus text endpoint. Served on its own listener (`METRICS_ADDR`) so
+/// it never shares a port with the public API.
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(state)
+}
+
+async fn render(State(state): State<AppState>) -> impl IntoResponse {
+    let mut out = String::new();
+    let memory = &state.memory;
+
+    gauge(
+        &mut out,
+        "ghostbin_redis_used_memory_bytes",
+        "Redis used_memory at the last sample plus bytes written since.",
+        Some(memory.used_bytes()),
+    );
+    gauge(
+        &mut out,
+        "ghostbin_redis_memory_limit_bytes",
+        "Redis maxmemory, or REDIS_MEMORY_LIMIT_BYTES when set.",
+        memory.limit_bytes(),
+    );
+    gauge(
+        &mut out,
+        "ghostbin_redis_memory_headroom_bytes",
+        "Bytes left before new pastes are refused.",
+        memory.headroom_bytes(),
+    );
+    gauge(
+        &mut out,
+        "ghostbin_redis_circuit_open",
+        "1 while the Redis circuit breaker is failing calls fast.",
+        Some(state.repository.breaker().is_open() as u64),
+    );
+    counter(
+        &mut out,
+        "ghostbin_pastes_rejected_storage_full_total",
+        "Pastes refused because Redis was past the high-water mark.",
+        memory.rejected_total(),
+    );
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
+
+/// Unknown values are left out rather than reported as 0.
+fn gauge(out: &mut String, name: &str, help: &str, value: Option<u64>) {
+    if let Some(value) = value {
+        let _ = writeln!(
+            out,
+            "# HELP {} {}\n# TYPE {} gauge\n{} {}",
+            name, help, name, name, value
+        );
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(
+        out,
+        "# HELP {} {}\n# TYPE {} counter\n{} {}",
+        name, help, name, name, value
+    );
+}
@@ -15,6 +15,13 @@ pub struct Paste {
     pub encrypted_key: Option<String>,
     pub key_iv: Option<String>,
     pub burn_token_hash: Option<String>,
+    /// SHA-256 of the access proof reads must present. Never sent to readers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_hash: Option<String>,
+    /// SHA-256 of the read token every request about the paste must present.
+    /// Never sent to readers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_hash: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -31,6 +38,58 @@ pub struct CreatePasteRequest {
     pub encrypted_key: Option<String>,
     pub key_iv: Option<String>,
     pub burn_token_hash: Option<String>,
+    /// Hex SHA-256 of a verifier the client derives from the password. When
+    /// set, reads must present the verifier as `X-Access-Proof`.
+    pub access_hash: Option<String>,
+    /// Hex SHA-256 of a read token the client derives from the link. When
+    /// set, the paste reads as missing to anyone not presenting the token as
+    /// `X-Read-Token`.
+    pub read_hash: Option<String>,
+}
+
+/// A moderation blocklist entry. While it exists the paste reads as missing,
+/// whether or not it is still stored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Block {
+    pub reason: Option<String>,
+    /// Unix seconds.
+    pub blocked_at: i64,
+}
+
+/// Why a paste was reported.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportReason {
+    Spam,
+    Phishing,
+    Malware,
+    Illegal,
+    Harassment,
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportRequest {
+    pub reason: ReportReason,
+    pub message: Option<String>,
+    /// The paste's decryption key, if the reporter wants moderators to be
+    /// able to read what they are judging.
+    pub key: Option<String>,
+}
+
+/// A report waiting for a moderator.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub id: String,
+    pub paste_id: String,
+    pub reason: ReportReason,
+    pub message: Option<String>,
+    pub key: Option<String>,
+    /// Unix seconds.
+    pub reported_at: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -66,6 +125,36 @@ impl CreatePasteRequest {
             }
         }
 
+        if let Some(ref hash) = self.access_hash {
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err("Access hash must be 64 hex digits".to_string());
+            }
+        }
+
+        if let Some(ref hash) = self.read_hash {
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err("Read hash must be 64 hex digits".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ReportRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref message) = self.message {
+            if message.chars().count() > 1000 {
+                return Err("Message too long".to_string());
+            }
+        }
+
+        if let Some(ref key) = self.key {
+            if key.len() > 512 {
+                return Err("Key too long".to_string());
+            }
+        }
+
         Ok(())
     }
 }
@@ -88,6 +177,8 @@ mod tests {
             encrypted_key: None,
             key_iv: None,
             burn_token_hash: None,
+            access_hash: None,
+            read_hash: None,
         };
         assert!(req.validate().is_err());
     }
@@ -107,6 +198,8 @@ mod tests {
             encrypted_key: None,
             key_iv: None,
             burn_token_hash: None,
+            access_hash: None,
+            read_hash: None,
         };
         assert!(req.validate().is_err());
 
@@ -122,7 +215,55 @@ mod tests {
             encrypted_key: None,
             key_iv: None,
             burn_token_hash: None,
+            access_hash: None,
+            read_hash: None,
+        };
+        assert!(req.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_access_hash() {
+        let mut req = CreatePasteRequest {
+            iv: "iv".to_string(),
+            data: "data".to_string(),
+            created_at: 0,
+            expires_at: None,
+            burn_after_read: false,
+            views: 0,
+            has_password: true,
+            salt: None,
+            encrypted_key: None,
+            key_iv: None,
+            burn_token_hash: None,
+            access_hash: Some("ab".repeat(32)),
+            read_hash: None,
         };
+        assert!(req.validate().is_ok());
+        req.access_hash = Some("zz".repeat(32));
+        assert!(req.validate().is_err());
+        req.access_hash = Some("ab".to_string());
+        assert!(req.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_read_hash() {
+        let mut req = CreatePasteRequest {
+            iv: "iv".to_string(),
+            data: "data".to_string(),
+            created_at: 0,
+            expires_at: None,
+            burn_after_read: false,
+            views: 0,
+            has_password: false,
+            salt: None,
+            encrypted_key: None,
+            key_iv: None,
+            burn_token_hash: None,
+            access_hash: None,
+            read_hash: Some("cd".repeat(32)),
+        };
+        assert!(req.validate().is_ok());
+        req.read_hash = Some("cd".repeat(31));
         assert!(req.validate().is_err());
     }
 
@@ -140,6 +281,8 @@ mod tests {
             encrypted_key: None,
             key_iv: None,
             burn_token_hash: None,
+            access_hash: None,
+            read_hash: None,
         };
         assert!(req.validate().is_ok());
     }
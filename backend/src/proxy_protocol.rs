@@ -0,0 +1,160 @@
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_PREFIX: &[u8; 5] = b"PROXY";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Reads a HAProxy PROXY protocol header (v1 text or v2 binary) and returns
+/// the source address. `None` means an `UNKNOWN`/`LOCAL` header, in which case
+/// the caller falls back to the socket peer address.
+pub async fn read_header<R>(stream: &mut R) -> io::Result<Option<SocketAddr>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut prefix = [0u8; 5];
+    stream.read_exact(&mut prefix).await?;
+
+    if &prefix == V1_PREFIX {
+        read_v1(stream).await
+    } else if prefix == V2_SIGNATURE[..5] {
+        read_v2(stream).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+async fn read_v1<R>(stream: &mut R) -> io::Result<Option<SocketAddr>>
+where
+    R: AsyncRead + Unpin,
+{
+    // Byte-wise so nothing past the header is consumed from the socket.
+    let mut line = Vec::with_capacity(V1_MAX_LEN);
+    line.extend_from_slice(V1_PREFIX);
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(invalid("PROXY v1 header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+    parse_v1(line)
+}
+
+fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let mut parts = line.split(' ');
+    parts.next(); // "PROXY"
+
+    match parts.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid("unsupported PROXY v1 protocol")),
+    }
+
+    let src_ip: IpAddr = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid("invalid PROXY v1 source address"))?;
+    let _dst_ip = parts.next();
+    let src_port: u16 = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid("invalid PROXY v1 source port"))?;
+
+    Ok(Some(SocketAddr::new(src_ip, src_port)))
+}
+
+async fn read_v2<R>(stream: &mut R) -> io::Result<Option<SocketAddr>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut rest = [0u8; 11];
+    stream.read_exact(&mut rest).await?;
+    if rest[..7] != V2_SIGNATURE[5..] {
+        return Err(invalid("invalid PROXY v2 signature"));
+    }
+
+    let ver_cmd = rest[7];
+    let family = rest[8];
+    let len = u16::from_be_bytes([rest[9], rest[10]]) as usize;
+
+    if ver_cmd >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await?;
+
+    // LOCAL command: health checks from the proxy itself.
+    if ver_cmd & 0x0f == 0 {
+        return Ok(None);
+    }
+
+    match family >> 4 {
+        0x1 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let port = u16::from_be_bytes([body[8], body[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        0x2 if body.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&body[..16]);
+            let port = u16::from_be_bytes([body[32], body[33]]);
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(octets)),
+                port,
+            )))
+        }
+        0x0 => Ok(None),
+        _ => Err(invalid("unsupported PROXY v2 address family")),
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_v1_tor_circuit_header() {
+        let mut input: &[u8] = b"PROXY TCP6 fc00:dead:beef:4dad::0:29 ::1 65535 42\r\nGET /";
+        let src = read_header(&mut input).await.unwrap().unwrap();
+        assert_eq!(
+            src.ip(),
+            "fc00:dead:beef:4dad::29".parse::<IpAddr>().unwrap()
+        );
+        // The request bytes after the header must be left untouched.
+        assert_eq!(input, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn test_v1_unknown() {
+        let mut input: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_header(&mut input).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_v2_tcp4() {
+        let mut input = V2_SIGNATURE.to_vec();
+        input.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        input.extend_from_slice(&[203, 0, 113, 7, 10, 0, 0, 1, 0x1f, 0x90, 0, 80]);
+        input.extend_from_slice(b"GET /");
+
+        let mut reader = input.as_slice();
+        let src = read_header(&mut reader).await.unwrap().unwrap();
+        assert_eq!(src, "203.0.113.7:8080".parse().unwrap());
+        assert_eq!(reader, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_plain_http() {
+        let mut input: &[u8] = b"GET / HTTP/1.1\r\n\r\n";
+        assert!(read_header(&mut input).await.is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::breaker::CircuitBreaker;
+use crate::client::ClientIdentity;
+use crate::config::{RateLimitConfig, RedisConfig};
+use crate::db::{Pool, Store};
+#[cfg(feature = "embedded-store")]
+use crate::embedded_store::EmbeddedStore;
+use crate::error::AppError;
+use crate::keys::Keys;
+use crate::repository::DEFAULT_COMMAND_TIMEOUT;
+use crate::tenant::Tenant;
+use axum::{
+    extract::{MatchedPath, Request},
+    http::Method,
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use deadpool_redis::redis::Script;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+/// Atomic token bucket. Uses the Redis clock so replicas with skewed clocks
+/// still agree on refill.
+static TOKEN_BUCKET: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"-- ghostbin:token_bucket
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3])
+local t = redis.call('TIME')
+local now = tonumber(t[1]) * 1000 + math.floor(tonumber(t[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(state[1]) or capacity
+local ts = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate / 1000)
+local allowed = 0
+local retry_ms = 0
+if tokens >= cost then
+  tokens = tokens - cost
+  allowed = 1
+else
+  retry_ms = math.ceil((cost - tokens) * 1000 / rate)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity * 1000 / rate) + 1000)
+return {allowed, retry_ms}
+"#,
+    )
+});
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LimitedRoute {
+    Challenge,
+    Create,
+    Read,
+    Metadata,
+    Delete,
+    Report,
+    /// Not a route: identical reports about one paste, whoever sends them.
+    ReportRepeat,
+}
+
+impl LimitedRoute {
+    pub const ALL: [LimitedRoute; 7] = [
+        LimitedRoute::Challenge,
+        LimitedRoute::Create,
+        LimitedRoute::Read,
+        LimitedRoute::Metadata,
+        LimitedRoute::Delete,
+        LimitedRoute::Report,
+        LimitedRoute::ReportRepeat,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LimitedRoute::Challenge => "challenge",
+            LimitedRoute::Create => "create",
+            LimitedRoute::Read => "read",
+            LimitedRoute::Metadata => "metadata",
+            LimitedRoute::Delete => "delete",
+            LimitedRoute::Report => "report",
+            LimitedRoute::ReportRepeat => "report_repeat",
+        }
+    }
+
+    pub fn default_policy(self) -> RateLimitPolicy {
+        match self {
+            LimitedRoute::Challenge => RateLimitPolicy::new(200, 50.0),
+            LimitedRoute::Create => RateLimitPolicy::new(50, 10.0),
+            LimitedRoute::Read | LimitedRoute::Metadata => RateLimitPolicy::new(200, 50.0),
+            LimitedRoute::Delete => RateLimitPolicy::new(50, 10.0),
+            LimitedRoute::Report => RateLimitPolicy::new(10, 0.1),
+            // One per hour.
+            LimitedRoute::ReportRepeat => RateLimitPolicy::new(1, 1.0 / 3600.0),
+        }
+    }
+
+    fn from_request(method: &Method, path: &str) -> Option<Self> {
+        match (method, path) {
+            (&Method::GET, "/api/v1/challenge") => Some(LimitedRoute::Challenge),
+            (&Method::POST, "/api/v1/paste") => Some(LimitedRoute::Create),
+            (&Method::GET, "/api/v1/paste/:id") => Some(LimitedRoute::Read),
+            (&Method::DELETE, "/api/v1/paste/:id") | (&Method::POST, "/api/v1/paste/:id/ack") => {
+                Some(LimitedRoute::Delete)
+            }
+            (&Method::GET, "/api/v1/paste/:id/metadata") => Some(LimitedRoute::Metadata),
+            (&Method::POST, "/api/v1/paste/:id/report") => Some(LimitedRoute::Report),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitPolicy {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl RateLimitPolicy {
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let (burst, per_second) = value.split_once(',')?;
+        let burst: u32 = burst.trim().parse().ok()?;
+        let per_second: f64 = per_second.trim().parse().ok()?;
+        if burst == 0 || !per_second.is_finite() || per_second <= 0.0 {
+            return None;
+        }
+        Some(Self::new(burst, per_second))
+    }
+}
+
+/// Redis-backed token buckets shared by every backend replica, keyed per
+/// route and per client (Tor circuit or IP, see `ClientIdentity`). If Redis
+/// cannot be reached the limiter fails open and the per-process semaphores in
+/// `AppState` remain the only bound.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Store,
+    policies: Arc<HashMap<LimitedRoute, RateLimitPolicy>>,
+    command_timeout: Duration,
+    breaker: CircuitBreaker,
+    keys: Keys,
+}
+
+impl RateLimiter {
+    pub fn new(pool: Pool, config: &RateLimitConfig) -> Self {
+        Self::with_store(Store::Redis(pool), config)
+    }
+
+    /// Keeps buckets in this process instead of Redis.
+    #[cfg(feature = "embedded-store")]
+    pub fn embedded(store: Arc<EmbeddedStore>, config: &RateLimitConfig) -> Self {
+        Self::with_store(Store::Embedded(store), config)
+    }
+
+    fn with_store(store: Store, config: &RateLimitConfig) -> Self {
+        Self {
+            store,
+            policies: Arc::new(HashMap::new()),
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            breaker: CircuitBreaker::new(&RedisConfig::default()),
+            keys: Keys::default(),
+        }
+        .with_policies(config)
+    }
+
+    /// Same Redis, different limits (e.g. for a tenant).
+    pub fn with_policies(mut self, config: &RateLimitConfig) -> Self {
+        let mut policies = config.policies.clone();
+        if !config.enabled {
+            // Keyed by report, not by client, so it needs no client identity.
+            policies.retain(|route, _| *route == LimitedRoute::ReportRepeat);
+        }
+        self.policies = Arc::new(policies);
+        self
+    }
+
+    /// Keeps buckets under `keys`' prefix.
+    pub fn with_keys(mut self, keys: Keys) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// While the shared breaker is open, checks skip Redis and fail open.
+    pub fn with_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = breaker;
+        self
+    }
+
+    /// A timed-out check fails open like any other Redis error.
+    pub fn with_command_timeout(mut self, command_timeout: Duration) -> Self {
+        self.command_timeout = command_timeout;
+        self
+    }
+
+    /// Takes one token from the bucket for `route` and `client`.
+    pub async fn check(&self, route: LimitedRoute, client: &str) -> Result<(), AppError> {
+        let Some(policy) = self.policies.get(&route) else {
+            return Ok(());
+        };
+
+        let key = self.keys.rate_limit(route.name(), client);
+        let taken = self
+            .breaker
+            .call(async {
+                tokio::time::timeout(self.command_timeout, self.take(&key, policy))
+                    .await
+                    .unwrap_or(Err(AppError::StorageTimeout))
+            })
+            .await;
+        let (allowed, retry_ms) = match taken {
+            Ok(result) => result,
+            // Already logged when the breaker opened.
+            Err(AppError::StorageUnavailable { .. }) if self.breaker.is_open() => return Ok(()),
+            Err(err) => {
+                tracing::warn!(error = ?err, route = route.name(), "rate limiter unavailable");
+                return Ok(());
+            }
+        };
+
+        if allowed == 1 {
+            Ok(())
+        } else {
+            Err(AppError::TooManyRequests {
+                retry_after_secs: retry_ms.max(1).div_ceil(1000),
+            })
+        }
+    }
+
+    async fn take(&self, key: &str, policy: &RateLimitPolicy) -> Result<(i64, u64), AppError> {
+        match &self.store {
+            Store::Redis(pool) => {
+                let mut con = pool.get().await?;
+                let result = TOKEN_BUCKET
+                    .key(key)
+                    .arg(policy.burst)
+                    .arg(policy.per_second)
+                    .arg(1)
+                    .invoke_async(&mut con)
+                    .await?;
+                Ok(result)
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                let ttl = Duration::from_millis(
+                    (policy.burst as f64 * 1000.0 / policy.per_second).ceil() as u64 + 1000,
+                );
+                store.update(key, ttl, |state| take_local(state, policy, unix_millis()))
+            }
+        }
+    }
+}
+
+/// `TOKEN_BUCKET` for the embedded store, whose updates are already atomic.
+/// The bucket is stored as `tokens,ts`.
+#[cfg(feature = "embedded-store")]
+fn take_local(state: Option<&[u8]>, policy: &RateLimitPolicy, now: u64) -> (Vec<u8>, (i64, u64)) {
+    let capacity = policy.burst as f64;
+    let (tokens, ts) = state
+        .and_then(|state| std::str::from_utf8(state).ok()?.split_once(','))
+        .and_then(|(tokens, ts)| Some((tokens.parse().ok()?, ts.parse().ok()?)))
+        .unwrap_or((capacity, now));
+    let elapsed = now.saturating_sub(ts) as f64;
+    let mut tokens: f64 = capacity.min(tokens + elapsed * policy.per_second / 1000.0);
+    let (allowed, retry_ms) = if tokens >= 1.0 {
+        tokens -= 1.0;
+        (1, 0)
+    } else {
+        (
+            0,
+            ((1.0 - tokens) * 1000.0 / policy.per_second).ceil() as u64,
+        )
+    };
+    (
+        format!("{},{}", tokens, now).into_bytes(),
+        (allowed, retry_ms),
+    )
+}
+
+#[cfg(feature = "embedded-store")]
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+pub async fn enforce(
+    Extension(tenant): Extension<Arc<Tenant>>,
+    matched: MatchedPath,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if let Some(route) = LimitedRoute::from_request(request.method(), matched.as_str()) {
+        // Requests that did not come through our listener (e.g. tests driving
+        // the router directly) share a single bucket.
+        let client = request
+            .extensions()
+            .get::<ClientIdentity>()
+            .map(ClientIdentity::rate_limit_key)
+            .unwrap_or_else(|| "global".to_string());
+        tenant.rate_limiter.check(route, &client).await?;
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(
+            RateLimitPolicy::parse("20, 2.5"),
+            Some(RateLimitPolicy::new(20, 2.5))
+        );
+        assert_eq!(RateLimitPolicy::parse("20"), None);
+        assert_eq!(RateLimitPolicy::parse("0,1"), None);
+        assert_eq!(RateLimitPolicy::parse("10,0"), None);
+    }
+
+    #[cfg(feature = "embedded-store")]
+    #[test]
+    fn test_take_local_refills() {
+        let policy = RateLimitPolicy::new(2, 10.0);
+        let (state, taken) = take_local(None, &policy, 1_000);
+        assert_eq!(taken, (1, 0));
+        let (state, taken) = take_local(Some(&state), &policy, 1_000);
+        assert_eq!(taken, (1, 0));
+        let (state, taken) = take_local(Some(&state), &policy, 1_000);
+        assert_eq!(taken, (0, 100));
+        // 10 tokens per second: one is back after 100 ms.
+        let (_, taken) = take_local(Some(&state), &policy, 1_100);
+        assert_eq!(taken, (1, 0));
+    }
+}
@@ -1,140 +1,635 @@
+use crate::admin::WipeReport;
+use crate::breaker::CircuitBreaker;
+use crate::config::RedisConfig;
+use crate::db::{self, Connection, Pool, Store};
+#[cfg(feature = "embedded-store")]
+use crate::embedded_store::{EmbeddedStore, Expiry, SetCondition};
+use crate::envelope::RecordCipher;
 use crate::error::AppError;
-use crate::model::Paste;
+use crate::keys::Keys;
+use crate::model::{Block, Paste, Report};
 use deadpool_redis::redis::AsyncCommands;
-use deadpool_redis::Pool;
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+#[cfg(feature = "embedded-store")]
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A per-paste secret whose wrong guesses are counted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Secret {
+    AccessProof,
+    BurnToken,
+}
+
+impl Secret {
+    fn name(self) -> &'static str {
+        match self {
+            Secret::AccessProof => "access",
+            Secret::BurnToken => "burn",
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct PasteRepository {
-    pool: Pool,
+    store: Store,
+    command_timeout: Duration,
+    breaker: CircuitBreaker,
+    connect_retries: u32,
+    retry_backoff: Duration,
+    keys: Keys,
+    cipher: Option<RecordCipher>,
 }
 
 impl PasteRepository {
     pub fn new(pool: Pool) -> Self {
-        Self { pool }
+        Self::with_store(Store::Redis(pool))
     }
 
-    pub async fn save_paste(&self, paste: Paste, ttl_seconds: u64) -> Result<(), AppError> {
-        let mut con = self
-            .pool
-            .get()
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
-
-        let key = format!("paste:{}", paste.id);
-        let json = serde_json::to_string(&paste).map_err(|_| AppError::InternalServerError)?;
-
-        let result: Option<String> = deadpool_redis::redis::cmd("SET")
-            .arg(&key)
-            .arg(json)
-            .arg("NX")
-            .arg("EX")
-            .arg(ttl_seconds)
-            .query_async(&mut con)
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
+    /// Keeps pastes in this process instead of Redis.
+    #[cfg(feature = "embedded-store")]
+    pub fn embedded(store: Arc<EmbeddedStore>) -> Self {
+        Self::with_store(Store::Embedded(store))
+    }
 
-        if result.is_none() {
-            return Err(AppError::Conflict("Paste ID already exists".to_string()));
+    fn with_store(store: Store) -> Self {
+        let redis = RedisConfig::default();
+        Self {
+            store,
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            breaker: CircuitBreaker::new(&redis),
+            connect_retries: redis.connect_retries,
+            retry_backoff: redis.retry_backoff,
+            keys: Keys::default(),
+            cipher: None,
         }
+    }
 
-        Ok(())
+    /// Stores everything under `keys`' prefix.
+    pub fn with_keys(mut self, keys: Keys) -> Self {
+        self.keys = keys;
+        self
     }
 
-    pub async fn get_paste(&self, id: &str) -> Result<Option<Paste>, AppError> {
-        let mut con = self
-            .pool
-            .get()
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
+    pub fn keys(&self) -> &Keys {
+        &self.keys
+    }
 
-        let key = format!("paste:{}", id);
-        let json: Option<String> = con
-            .get(&key)
+    /// Seals every stored record with `cipher`. Records it cannot open, such
+    /// as those written under a previous key, read as missing.
+    pub fn with_cipher(mut self, cipher: RecordCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Shares `breaker` with other Redis users (e.g. the rate limiter) so
+    /// they all see the same view of Redis health.
+    pub fn with_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = breaker;
+        self
+    }
+
+    pub fn with_connect_retry(mut self, retries: u32, backoff: Duration) -> Self {
+        self.connect_retries = retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    /// Bounds every call, including the wait for a pooled connection, so a
+    /// stalled Redis cannot pin a request (and its semaphore permit) forever.
+    pub fn with_command_timeout(mut self, command_timeout: Duration) -> Self {
+        self.command_timeout = command_timeout;
+        self
+    }
+
+    async fn timed<T>(
+        &self,
+        call: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        self.breaker
+            .call(async {
+                tokio::time::timeout(self.command_timeout, call)
+                    .await
+                    .map_err(|_| AppError::StorageTimeout)?
+            })
             .await
-            .map_err(|_| AppError::InternalServerError)?;
+    }
+
+    async fn connection(&self, pool: &Pool) -> Result<Connection, AppError> {
+        Ok(db::connect(pool, self.connect_retries, self.retry_backoff).await?)
+    }
+
+    /// Serializes `value`, sealed to `id` when records are encrypted.
+    fn encode(&self, id: &str, value: &impl Serialize) -> Result<Vec<u8>, AppError> {
+        let json = serde_json::to_vec(value)?;
+        Ok(match &self.cipher {
+            Some(cipher) => cipher.seal(id, &json),
+            None => json,
+        })
+    }
+
+    fn decode<T: DeserializeOwned>(&self, id: &str, record: &[u8]) -> Result<Option<T>, AppError> {
+        let json = match &self.cipher {
+            Some(cipher) => match cipher.open(id, record) {
+                Some(json) => json,
+                None => return Ok(None),
+            },
+            None => record.to_vec(),
+        };
+        Ok(Some(serde_json::from_slice(&json)?))
+    }
+
+    /// Stores a new paste. An ID that is taken, or blocklisted, is a conflict.
+    pub async fn save_paste(&self, paste: Paste, ttl_seconds: u64) -> Result<(), AppError> {
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+
+                    let blocked: bool = con.exists(self.keys.blocked(&paste.id)).await?;
+                    if blocked {
+                        return Err(AppError::PasteIdConflict);
+                    }
+
+                    let key = self.keys.paste(&paste.id);
+                    let record = self.encode(&paste.id, &paste)?;
+
+                    let result: Option<String> = deadpool_redis::redis::cmd("SET")
+                        .arg(&key)
+                        .arg(record)
+                        .arg("NX")
+                        .arg("EX")
+                        .arg(ttl_seconds)
+                        .query_async(&mut con)
+                        .await?;
 
-        match json {
-            Some(j) => {
-                let paste: Paste =
-                    serde_json::from_str(&j).map_err(|_| AppError::InternalServerError)?;
-                Ok(Some(paste))
+                    if result.is_none() {
+                        return Err(AppError::PasteIdConflict);
+                    }
+
+                    Ok(())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                if store.get(&self.keys.blocked(&paste.id)).is_some() {
+                    return Err(AppError::PasteIdConflict);
+                }
+                let record = self.encode(&paste.id, &paste)?;
+                let ttl = Expiry::After(Duration::from_secs(ttl_seconds));
+                let key = self.keys.paste(&paste.id);
+                if !store.set(&key, record, SetCondition::IfAbsent, ttl)? {
+                    return Err(AppError::PasteIdConflict);
+                }
+                Ok(())
             }
-            None => Ok(None),
         }
     }
 
-    pub async fn increment_views(&self, mut paste: Paste) -> Result<Paste, AppError> {
-        let mut con = self
-            .pool
-            .get()
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
+    /// The paste, unless it is blocklisted.
+    pub async fn get_paste(&self, id: &str) -> Result<Option<Paste>, AppError> {
+        Ok(match self.inspect(id).await? {
+            (paste, None) => paste,
+            (_, Some(_)) => None,
+        })
+    }
 
-        let key = format!("paste:{}", paste.id);
-        paste.views += 1;
+    /// The paste and its blocklist entry, each if present.
+    pub async fn inspect(&self, id: &str) -> Result<(Option<Paste>, Option<Block>), AppError> {
+        let (record, block) = match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
 
-        let new_json = serde_json::to_string(&paste).map_err(|_| AppError::InternalServerError)?;
+                    // Same hash tag, so one MGET even on a cluster.
+                    let (record, block): (Option<Vec<u8>>, Option<Vec<u8>>) =
+                        deadpool_redis::redis::cmd("MGET")
+                            .arg(self.keys.paste(id))
+                            .arg(self.keys.blocked(id))
+                            .query_async(&mut con)
+                            .await?;
+                    Ok((record, block))
+                })
+                .await?
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => (
+                store.get(&self.keys.paste(id)),
+                store.get(&self.keys.blocked(id)),
+            ),
+        };
+        let paste = match record {
+            Some(record) => self.decode(id, &record)?,
+            None => None,
+        };
+        Ok((paste, block.as_deref().map(decode_block)))
+    }
+
+    /// Like `get_paste`, but served from a replica when replica reads are on.
+    /// Replicas lag, so a miss (the paste may be seconds old) and any
+    /// burn-after-read paste (it may have just burned) are confirmed on the
+    /// primary.
+    pub async fn get_paste_for_read(&self, id: &str) -> Result<Option<Paste>, AppError> {
+        let replica = match &self.store {
+            Store::Redis(pool) => self.read_replica(pool, id).await,
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(_) => None,
+        };
+        match replica {
+            Some(paste) if !paste.burn_after_read => Ok(Some(paste)),
+            _ => self.get_paste(id).await,
+        }
+    }
 
-        let _: () = deadpool_redis::redis::cmd("SET")
-            .arg(&key)
-            .arg(new_json)
-            .arg("KEEPTTL")
-            .query_async(&mut con)
+    /// Best effort: any replica failure just means asking the primary, and
+    /// does not count against the primary's circuit breaker.
+    async fn read_replica(&self, pool: &Pool, id: &str) -> Option<Paste> {
+        let read = async {
+            let mut con = pool.get_replica().await?.ok()?;
+            let (record, block): (Option<Vec<u8>>, Option<Vec<u8>>) =
+                deadpool_redis::redis::cmd("MGET")
+                    .arg(self.keys.paste(id))
+                    .arg(self.keys.blocked(id))
+                    .query_async(&mut con)
+                    .await
+                    .ok()?;
+            if block.is_some() {
+                return None;
+            }
+            self.decode(id, &record?).ok().flatten()
+        };
+        tokio::time::timeout(self.command_timeout, read)
             .await
-            .map_err(|_| AppError::InternalServerError)?;
+            .ok()
+            .flatten()
+    }
+
+    pub async fn increment_views(&self, mut paste: Paste) -> Result<Paste, AppError> {
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+
+                    let key = self.keys.paste(&paste.id);
+                    paste.views += 1;
+
+                    let record = self.encode(&paste.id, &paste)?;
+
+                    // XX: `paste` may be a replica's copy of a paste that has since
+                    // been deleted or expired on the primary; never recreate it.
+                    let stored: Option<String> = deadpool_redis::redis::cmd("SET")
+                        .arg(&key)
+                        .arg(record)
+                        .arg("XX")
+                        .arg("KEEPTTL")
+                        .query_async(&mut con)
+                        .await?;
+                    if stored.is_none() {
+                        return Err(AppError::PasteNotFound);
+                    }
 
-        Ok(paste)
+                    Ok(paste)
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                paste.views += 1;
+                let record = self.encode(&paste.id, &paste)?;
+                let key = self.keys.paste(&paste.id);
+                if !store.set(&key, record, SetCondition::IfPresent, Expiry::Keep)? {
+                    return Err(AppError::PasteNotFound);
+                }
+                Ok(paste)
+            }
+        }
     }
 
-    pub async fn set_burn_timeout(&self, id: &str, seconds: u64) -> Result<(), AppError> {
-        let mut con = self
-            .pool
-            .get()
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
+    /// Leases burn-after-read paste `id` to the caller for `lease`. Returns
+    /// `false` while someone else holds it.
+    pub async fn take_lease(&self, id: &str, lease: Duration) -> Result<bool, AppError> {
+        let key = self.keys.lease(id);
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+                    let taken: Option<String> = deadpool_redis::redis::cmd("SET")
+                        .arg(&key)
+                        .arg(1)
+                        .arg("NX")
+                        .arg("EX")
+                        .arg(lease.as_secs().max(1))
+                        .query_async(&mut con)
+                        .await?;
+                    Ok(taken.is_some())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => store.set(
+                &key,
+                b"1".to_vec(),
+                SetCondition::IfAbsent,
+                Expiry::After(lease),
+            ),
+        }
+    }
 
-        let key = format!("paste:{}", id);
-        let _: () = con
-            .expire(&key, seconds as i64)
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
-        Ok(())
+    /// Brings paste `id`'s expiry forward to `ttl` from now, unless it was
+    /// due sooner anyway.
+    pub async fn expire_paste(&self, id: &str, ttl: Duration) -> Result<(), AppError> {
+        let key = self.keys.paste(id);
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+                    let _: i64 = deadpool_redis::redis::cmd("EXPIRE")
+                        .arg(&key)
+                        .arg(ttl.as_secs().max(1))
+                        .arg("LT")
+                        .query_async(&mut con)
+                        .await?;
+                    Ok(())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                store.expire_sooner(&key, ttl);
+                Ok(())
+            }
+        }
     }
 
+    /// Removes the paste and any burn lease on it.
     pub async fn delete_paste(&self, id: &str) -> Result<(), AppError> {
-        let mut con = self
-            .pool
-            .get()
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
 
-        let key = format!("paste:{}", id);
-        let _: () = con
-            .del(&key)
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
-        Ok(())
+                    // Same hash tag, so one DEL even on a cluster.
+                    let keys = [self.keys.paste(id), self.keys.lease(id)];
+                    let _: () = con.del(&keys).await?;
+                    Ok(())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                store.delete(&self.keys.paste(id));
+                store.delete(&self.keys.lease(id));
+                Ok(())
+            }
+        }
     }
 
     pub async fn mark_salt_used(&self, salt: &str) -> Result<bool, AppError> {
-        let mut con = self
-            .pool
-            .get()
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
 
-        let salt_key = format!("pow:salt:{}", salt);
+                    let salt_key = self.keys.pow_salt(salt);
 
-        let set_result: Option<String> = deadpool_redis::redis::cmd("SET")
-            .arg(&salt_key)
-            .arg("used")
-            .arg("NX")
-            .arg("EX")
-            .arg(120)
-            .query_async(&mut con)
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
+                    let set_result: Option<String> = deadpool_redis::redis::cmd("SET")
+                        .arg(&salt_key)
+                        .arg("used")
+                        .arg("NX")
+                        .arg("EX")
+                        .arg(120)
+                        .query_async(&mut con)
+                        .await?;
 
-        Ok(set_result.is_some())
+                    Ok(set_result.is_some())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => store.set(
+                &self.keys.pow_salt(salt),
+                b"used".to_vec(),
+                SetCondition::IfAbsent,
+                Expiry::After(Duration::from_secs(120)),
+            ),
+        }
+    }
+
+    /// Blocklists `id` for `ttl_seconds`, replacing any earlier entry.
+    pub async fn block(&self, id: &str, block: &Block, ttl_seconds: u64) -> Result<(), AppError> {
+        let record = serde_json::to_vec(block)?;
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+                    let _: () = deadpool_redis::redis::cmd("SET")
+                        .arg(self.keys.blocked(id))
+                        .arg(record)
+                        .arg("EX")
+                        .arg(ttl_seconds)
+                        .query_async(&mut con)
+                        .await?;
+                    Ok(())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                let ttl = Expiry::After(Duration::from_secs(ttl_seconds));
+                store.set(&self.keys.blocked(id), record, SetCondition::Always, ttl)?;
+                Ok(())
+            }
+        }
     }
+
+    /// Returns whether `id` was blocklisted.
+    pub async fn unblock(&self, id: &str) -> Result<bool, AppError> {
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+                    let removed: u32 = con.del(self.keys.blocked(id)).await?;
+                    Ok(removed > 0)
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => Ok(store.delete(&self.keys.blocked(id))),
+        }
+    }
+
+    /// Counts a guess at `secret` for `id`, before it is checked, and returns
+    /// the new count in one atomic step, so concurrent guesses cannot all see
+    /// the same count. The count is forgotten `window` after the last guess,
+    /// or cleared by `clear_failures` once one is right.
+    pub async fn record_attempt(
+        &self,
+        id: &str,
+        secret: Secret,
+        window: Duration,
+    ) -> Result<u32, AppError> {
+        let key = self.keys.failures(id, secret.name());
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+                    let (count,): (u32,) = deadpool_redis::redis::pipe()
+                        .atomic()
+                        .incr(&key, 1)
+                        .expire(&key, window.as_secs() as i64)
+                        .ignore()
+                        .query_async(&mut con)
+                        .await?;
+                    Ok(count)
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => store.update(&key, window, |count| {
+                let count = count
+                    .and_then(|count| std::str::from_utf8(count).ok()?.parse::<u32>().ok())
+                    .unwrap_or(0)
+                    + 1;
+                (count.to_string().into_bytes(), count)
+            }),
+        }
+    }
+
+    /// Forgets the wrong `secret` guesses for `id`.
+    pub async fn clear_failures(&self, id: &str, secret: Secret) -> Result<(), AppError> {
+        let key = self.keys.failures(id, secret.name());
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+                    let _: () = con.del(&key).await?;
+                    Ok(())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                store.delete(&key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether stored records are sealed (`STORE_ENCRYPTION`).
+    pub fn seals_records(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Stores an abuse report about paste `report.paste_id`. Callers must not
+    /// pass one carrying a key unless `seals_records`.
+    pub async fn save_report(&self, report: &Report, ttl_seconds: u64) -> Result<(), AppError> {
+        let key = self.keys.report(&report.paste_id, &report.id);
+        let record = self.encode(&key, report)?;
+        match &self.store {
+            Store::Redis(pool) => {
+                self.timed(async {
+                    let mut con = self.connection(pool).await?;
+                    let _: () = deadpool_redis::redis::cmd("SET")
+                        .arg(&key)
+                        .arg(record)
+                        .arg("EX")
+                        .arg(ttl_seconds)
+                        .query_async(&mut con)
+                        .await?;
+                    Ok(())
+                })
+                .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => {
+                let ttl = Expiry::After(Duration::from_secs(ttl_seconds));
+                store.set(&key, record, SetCondition::Always, ttl)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Up to `limit` pending reports, oldest first.
+    pub async fn reports(&self, limit: usize) -> Result<Vec<Report>, AppError> {
+        let prefix = self.keys.report_prefix();
+        let records = match &self.store {
+            Store::Redis(pool) => {
+                self.breaker
+                    .call(db::get_prefix(pool, &prefix, limit, self.command_timeout))
+                    .await?
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => store.get_prefix(&prefix, limit),
+        };
+        let mut reports = Vec::with_capacity(records.len());
+        for (key, record) in records {
+            // Unreadable under the current key: left to expire.
+            if let Some(report) = self.decode::<Report>(&key, &record).ok().flatten() {
+                reports.push(report);
+            }
+        }
+        reports.sort_by_key(|report| report.reported_at);
+        Ok(reports)
+    }
+
+    /// Drops every report about `id`, returning how many there were.
+    pub async fn dismiss_reports(&self, id: &str) -> Result<u64, AppError> {
+        let prefix = self.keys.reports_for(id);
+        match &self.store {
+            Store::Redis(pool) => {
+                self.breaker
+                    .call(db::unlink_prefix(pool, &prefix, self.command_timeout))
+                    .await
+            }
+            #[cfg(feature = "embedded-store")]
+            Store::Embedded(store) => Ok(store.delete_prefix(&prefix)),
+        }
+    }
+
+    /// Destroys every paste, report and failure counter in this repository's
+    /// namespace, and with `pow_salts` every PoW replay marker as well.
+    pub async fn wipe(&self, pow_salts: bool) -> Result<WipeReport, AppError> {
+        let prefixes = [
+            self.keys.paste_prefix(),
+            self.keys.pow_salt_prefix(),
+            self.keys.report_prefix(),
+            self.keys.failures_prefix(),
+        ];
+        let mut removed = [0; 4];
+        for (prefix, count) in prefixes.iter().zip(&mut removed) {
+            if *prefix == self.keys.pow_salt_prefix() && !pow_salts {
+                continue;
+            }
+            *count = match &self.store {
+                Store::Redis(pool) => {
+                    self.breaker
+                        .call(db::unlink_prefix(pool, prefix, self.command_timeout))
+                        .await?
+                }
+                #[cfg(feature = "embedded-store")]
+                Store::Embedded(store) => store.delete_prefix(prefix),
+            };
+        }
+        Ok(WipeReport {
+            pastes: removed[0],
+            pow_salts: removed[1],
+            reports: removed[2],
+        })
+    }
+}
+
+/// An entry that cannot be parsed still blocks.
+fn decode_block(record: &[u8]) -> Block {
+    serde_json::from_slice(record).unwrap_or(Block {
+        reason: None,
+        blocked_at: 0,
+    })
 }
@@ -0,0 +1,57 @@
+use crate::config::Config;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Response},
+    middleware::Next,
+};
+use std::sync::Arc;
+
+/// Paste bodies are ciphertext of possibly burn-after-read notes; no cache
+/// between us and the client may keep a copy.
+const PASTE_PATH_PREFIX: &str = "/api/v1/paste";
+const NO_STORE: &str = "no-store, no-cache";
+
+/// Headers added to every response unless overridden through
+/// `SECURITY_HEADER_<NAME>`.
+pub fn defaults() -> Vec<(HeaderName, HeaderValue)> {
+    vec![
+        (
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        ),
+        (
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        ),
+        (
+            HeaderName::from_static("cross-origin-resource-policy"),
+            HeaderValue::from_static("same-origin"),
+        ),
+        (
+            HeaderName::from_static("permissions-policy"),
+            HeaderValue::from_static(
+                "accelerometer=(), camera=(), geolocation=(), microphone=(), payment=(), usb=()",
+            ),
+        ),
+    ]
+}
+
+pub async fn apply(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let no_store = request.uri().path().starts_with(PASTE_PATH_PREFIX);
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    for (name, value) in &config.security_headers.headers {
+        headers.insert(name.clone(), value.clone());
+    }
+    if no_store {
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(NO_STORE));
+        headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+    }
+    response
+}
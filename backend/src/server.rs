@@ -0,0 +1,152 @@
+use crate::client::ClientIdentity;
+use crate::listener::Listener;
+use crate::proxy_protocol;
+use crate::tls::TlsAcceptor;
+use axum::{extract::Request, Router};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use ipnet::IpNet;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower::ServiceExt;
+
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Default)]
+pub struct ServeOptions {
+    /// Expect a PROXY protocol header at the start of every connection. Only
+    /// enable this when every peer is a trusted proxy (e.g. the tor daemon).
+    pub proxy_protocol: bool,
+    /// Terminate TLS after the (optional) PROXY header.
+    pub tls: Option<TlsAcceptor>,
+    /// Close HTTP/1 connections that take longer than this to send the
+    /// request line and headers. `None` keeps hyper's default.
+    pub header_read_timeout: Option<Duration>,
+    /// Peers whose `X-Forwarded-For`/`X-Real-IP` name the client, such as
+    /// the nginx in front of us. See `ClientIdentity::forwarded`.
+    pub trusted_proxies: Arc<[IpNet]>,
+}
+
+pub async fn serve(listener: Listener, app: Router, options: ServeOptions) -> io::Result<()> {
+    loop {
+        let accepted = match &listener {
+            Listener::Tcp(listener) => listener.accept().await.map(|(stream, peer)| {
+                tokio::spawn(handle_connection(
+                    stream,
+                    Some(peer.ip()),
+                    app.clone(),
+                    options.clone(),
+                ));
+            }),
+            Listener::Unix(listener) => listener.accept().await.map(|(stream, _)| {
+                tokio::spawn(handle_connection(
+                    stream,
+                    None,
+                    app.clone(),
+                    options.clone(),
+                ));
+            }),
+        };
+
+        if let Err(err) = accepted {
+            // Usually EMFILE; back off instead of spinning.
+            tracing::warn!(error = %err, "accept failed");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// `peer` is `None` for Unix socket connections, which have no address until
+/// a PROXY header supplies one.
+async fn handle_connection<S>(
+    mut stream: S,
+    mut peer: Option<IpAddr>,
+    app: Router,
+    options: ServeOptions,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    if options.proxy_protocol {
+        let header = tokio::time::timeout(
+            PROXY_HEADER_TIMEOUT,
+            proxy_protocol::read_header(&mut stream),
+        )
+        .await;
+        match header {
+            Ok(Ok(Some(addr))) => peer = Some(addr.ip()),
+            Ok(Ok(None)) => {}
+            Ok(Err(err)) => {
+                tracing::debug!(error = %err, "rejected PROXY protocol header");
+                return;
+            }
+            Err(_) => return,
+        }
+    }
+
+    let identity = peer.map_or(ClientIdentity::Local, ClientIdentity::from_ip);
+    match options.tls {
+        Some(acceptor) => {
+            match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    serve_connection(
+                        stream,
+                        app,
+                        identity,
+                        options.header_read_timeout,
+                        options.trusted_proxies,
+                    )
+                    .await
+                }
+                Ok(Err(err)) => tracing::debug!(error = %err, "TLS handshake failed"),
+                Err(_) => tracing::debug!("TLS handshake timed out"),
+            }
+        }
+        None => {
+            serve_connection(
+                stream,
+                app,
+                identity,
+                options.header_read_timeout,
+                options.trusted_proxies,
+            )
+            .await
+        }
+    }
+}
+
+pub async fn serve_connection<S>(
+    stream: S,
+    app: Router,
+    identity: ClientIdentity,
+    header_read_timeout: Option<Duration>,
+    trusted_proxies: Arc<[IpNet]>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = app.map_request(move |mut request: Request<Incoming>| {
+        let identity = identity.forwarded(request.headers(), &trusted_proxies);
+        request.extensions_mut().insert(identity);
+        request
+    });
+
+    let mut builder = Builder::new(TokioExecutor::new());
+    if let Some(timeout) = header_read_timeout {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(timeout);
+    }
+
+    if let Err(err) = builder
+        .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+        .await
+    {
+        tracing::debug!(error = %err, "connection closed with error");
+    }
+}
@@ -0,0 +1,135 @@
+use crate::config::{CorsConfig, TtlPolicy};
+use crate::handlers::AppState;
+use crate::rate_limit::RateLimiter;
+use crate::repository::PasteRepository;
+use axum::{
+    extract::{Request, State},
+    http::{header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Everything that differs between branded hostnames. Each tenant has its
+/// own key namespace, so pastes, PoW salts and rate-limit buckets of one are
+/// invisible to every other.
+pub struct Tenant {
+    /// Empty for requests that matched no configured tenant.
+    pub name: String,
+    pub repository: PasteRepository,
+    pub rate_limiter: RateLimiter,
+    pub paste_ttl: TtlPolicy,
+    pub cors: CorsConfig,
+}
+
+/// Host-header lookup of the configured tenants. Hosts that match none get
+/// the instance-wide settings and namespace.
+#[derive(Clone)]
+pub struct Tenants {
+    default: Arc<Tenant>,
+    by_host: Arc<HashMap<String, Arc<Tenant>>>,
+}
+
+impl Tenants {
+    pub fn new(state: &AppState) -> Self {
+        let config = &state.config;
+        let default = Arc::new(Tenant {
+            name: String::new(),
+            repository: state.repository.clone(),
+            rate_limiter: state.rate_limiter.clone(),
+            paste_ttl: config.paste_ttl,
+            cors: config.cors.clone(),
+        });
+
+        let mut by_host = HashMap::new();
+        for tenant_config in &config.tenants {
+            let keys = state
+                .repository
+                .keys()
+                .nested(&format!("{}:", tenant_config.name));
+            let tenant = Arc::new(Tenant {
+                name: tenant_config.name.clone(),
+                repository: state.repository.clone().with_keys(keys.clone()),
+                rate_limiter: state
+                    .rate_limiter
+                    .clone()
+                    .with_policies(&tenant_config.rate_limit)
+                    .with_keys(keys),
+                paste_ttl: tenant_config.paste_ttl,
+                cors: tenant_config.cors.clone(),
+            });
+            for host in &tenant_config.hosts {
+                by_host.insert(host.clone(), tenant.clone());
+            }
+        }
+
+        Self {
+            default,
+            by_host: Arc::new(by_host),
+        }
+    }
+
+    /// The instance-wide tenant followed by every configured one.
+    pub fn all(&self) -> Vec<&Arc<Tenant>> {
+        let mut all = vec![&self.default];
+        for tenant in self.by_host.values() {
+            if !all.iter().any(|known| Arc::ptr_eq(known, tenant)) {
+                all.push(tenant);
+            }
+        }
+        all
+    }
+
+    /// `""` is the instance-wide tenant.
+    pub fn by_name(&self, name: &str) -> Option<&Arc<Tenant>> {
+        self.all().into_iter().find(|tenant| tenant.name == name)
+    }
+
+    pub fn resolve(&self, parts: &Parts) -> &Arc<Tenant> {
+        // HTTP/2 carries the host in the URI instead of a Host header.
+        let host = parts
+            .uri
+            .host()
+            .or_else(|| parts.headers.get(header::HOST)?.to_str().ok())
+            .map(|host| strip_port(host).to_ascii_lowercase());
+        host.and_then(|host| self.by_host.get(&host))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// `example.org:8443` → `example.org`, leaving IPv6 literals intact.
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if !port.is_empty()
+                && port.bytes().all(|b| b.is_ascii_digit())
+                && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            name
+        }
+        _ => host,
+    }
+}
+
+/// Attaches the request's `Arc<Tenant>` as an extension for the handlers,
+/// rate limiting and CORS.
+pub async fn resolve(State(tenants): State<Tenants>, request: Request, next: Next) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let tenant = tenants.resolve(&parts).clone();
+    parts.extensions.insert(tenant);
+    next.run(Request::from_parts(parts, body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("paste.example:8443"), "paste.example");
+        assert_eq!(strip_port("paste.example"), "paste.example");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+    }
+}
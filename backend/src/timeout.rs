@@ -0,0 +1,51 @@
+use crate::config::Config;
+use crate::error::AppError;
+use crate::MAX_BODY_BYTES;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use std::sync::Arc;
+
+/// Reads the whole body under `body_read` and runs the rest of the request
+/// under `request`, so neither a dribbling client nor a stalled backend call
+/// can hold a connection open indefinitely. Header reads are bounded by the
+/// connection itself (see `server::ServeOptions`).
+pub async fn enforce(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let timeouts = &config.timeouts;
+
+    let handle = async {
+        let (parts, body) = request.into_parts();
+        let collected = tokio::time::timeout(
+            timeouts.body_read,
+            Limited::new(body, MAX_BODY_BYTES).collect(),
+        )
+        .await
+        .map_err(|_| AppError::RequestTimeout)?;
+
+        let body = match collected {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) if err.downcast_ref::<LengthLimitError>().is_some() => {
+                return Err(AppError::PayloadTooLarge)
+            }
+            Err(_) => {
+                return Err(AppError::InvalidPayload(
+                    "Failed to read request body".into(),
+                ))
+            }
+        };
+
+        Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+    };
+
+    tokio::time::timeout(timeouts.request, handle)
+        .await
+        .unwrap_or(Err(AppError::DeadlineExceeded))
+}
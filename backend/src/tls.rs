@@ -0,0 +1,191 @@
+use crate::config::TlsConfig;
+use axum::{
+    extract::Request,
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_rustls::server::TlsStream;
+
+/// Serves whichever certificate was loaded last, so a reload takes effect for
+/// new handshakes without touching connections that are already open.
+struct ReloadableCert {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl fmt::Debug for ReloadableCert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadableCert")
+            .field("cert_path", &self.cert_path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResolvesServerCert for ReloadableCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> io::Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| pem_error(cert_path, err))?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("no certificates in {}", cert_path.display()),
+        ));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|err| pem_error(key_path, err))?;
+    let signing_key = ring::sign::any_supported_type(&key)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+fn pem_error(path: &Path, err: rustls::pki_types::pem::Error) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("{}: {:?}", path.display(), err),
+    )
+}
+
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    inner: tokio_rustls::TlsAcceptor,
+    certs: Arc<ReloadableCert>,
+}
+
+impl TlsAcceptor {
+    pub fn from_config(config: &TlsConfig) -> io::Result<Self> {
+        let key = load_certified_key(&config.cert_path, &config.key_path)?;
+        let certs = Arc::new(ReloadableCert {
+            cert_path: config.cert_path.clone(),
+            key_path: config.key_path.clone(),
+            current: RwLock::new(Arc::new(key)),
+        });
+
+        let mut server_config =
+            rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?
+                .with_no_client_auth()
+                .with_cert_resolver(certs.clone());
+        server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Self {
+            inner: tokio_rustls::TlsAcceptor::from(Arc::new(server_config)),
+            certs,
+        })
+    }
+
+    /// Re-reads the certificate and key from disk. On failure the previous
+    /// pair stays in use.
+    pub fn reload(&self) -> io::Result<()> {
+        let key = load_certified_key(&self.certs.cert_path, &self.certs.key_path)?;
+        *self.certs.current.write().unwrap() = Arc::new(key);
+        Ok(())
+    }
+
+    pub async fn accept<S>(&self, stream: S) -> io::Result<TlsStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.inner.accept(stream).await
+    }
+}
+
+pub fn reload_on_sighup(acceptor: TlsAcceptor) -> io::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match acceptor.reload() {
+                Ok(()) => tracing::info!("reloaded TLS certificate"),
+                Err(err) => {
+                    tracing::error!(error = %err, "TLS reload failed, keeping old certificate")
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Plain-HTTP app that sends every request to the same host and path over
+/// HTTPS on `https_port`.
+pub fn redirect_app(https_port: u16) -> Router {
+    Router::new().fallback(move |request: Request| async move {
+        redirect_to_https(
+            request.headers().get(header::HOST),
+            request.uri(),
+            https_port,
+        )
+    })
+}
+
+fn redirect_to_https(
+    host: Option<&axum::http::HeaderValue>,
+    uri: &Uri,
+    https_port: u16,
+) -> Response {
+    let Some(host) = host.and_then(|h| h.to_str().ok()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    // Drop any port from Host; the bracket check keeps IPv6 literals intact.
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') && port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    let authority = if https_port == 443 {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, https_port)
+    };
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+    Redirect::permanent(&format!("https://{}{}", authority, path)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirect_target() {
+        let uri: Uri = "/api/v1/challenge?x=1".parse().unwrap();
+
+        let host = axum::http::HeaderValue::from_static("example.org:8080");
+        let response = redirect_to_https(Some(&host), &uri, 443);
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://example.org/api/v1/challenge?x=1"
+        );
+
+        let host = axum::http::HeaderValue::from_static("[::1]");
+        let response = redirect_to_https(Some(&host), &uri, 8443);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://[::1]:8443/api/v1/challenge?x=1"
+        );
+
+        assert_eq!(
+            redirect_to_https(None, &uri, 443).status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use crate::config::Config;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use tokio::time::Instant;
+
+/// Length every metadata body is padded to in hardened mode. Larger than any
+/// body a paste made by the web client produces; only an outlandish salt
+/// sent through the raw API can push one past it.
+pub const METADATA_BODY_BYTES: usize = 1024;
+
+/// In hardened mode, holds the response back until `HARDENED_FLOOR_MS` after
+/// the request arrived, whatever the handler found, so the time a lookup
+/// takes says nothing about whether the paste exists. Errors are held back
+/// too: a miss is an error for `get_paste`.
+pub async fn pad(State(config): State<Arc<Config>>, request: Request, next: Next) -> Response {
+    if !config.hardening.enabled {
+        return next.run(request).await;
+    }
+    let deadline = Instant::now() + config.hardening.floor;
+    let response = next.run(request).await;
+    tokio::time::sleep_until(deadline).await;
+    response
+}
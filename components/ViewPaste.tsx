@@ -7,6 +7,7 @@ import { LoadingView, ErrorView } from './ViewPaste/StatusViews';
 interface ViewPasteProps {
   pasteId: string;
   decryptionKey: string | null;
+  capability: string | null;
   onBack: () => void;
   onFork: (content: string, language: string) => void;
 }
@@ -14,7 +15,8 @@ interface ViewPasteProps {
 export const ViewPaste: Component<ViewPasteProps> = (props) => {
   const { status, decryptedPaste, errorMsg, submitPassword } = usePasteViewer(
     () => props.pasteId,
-    () => props.decryptionKey
+    () => props.decryptionKey,
+    () => props.capability
   );
 
   return (
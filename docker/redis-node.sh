@@ -0,0 +1,63 @@
+#!/bin/sh
+# Starts a data node of docker-compose.ha.yml.
+#
+# Nodes keep nothing on disk, so a node always comes back empty. It must not
+# come back as the primary while a replica still holds the pastes: the
+# replica would resync from it and lose every one of them. The node asks the
+# Sentinels which node is the primary and replicates from it. If they still
+# name this node, because it restarted before they noticed it was gone, it
+# waits until they promote a live replica.
+#
+#   NODE_NAME        this node's hostname, as the Sentinels announce it
+#   INITIAL_PRIMARY  the primary of a fresh deployment
+#   SENTINELS        host:port of every Sentinel, space-separated
+#   SENTINEL_MASTER  the name the Sentinels monitor the primary under
+set -eu
+
+sentinel() {
+    for node in $SENTINELS; do
+        if answer=$(redis-cli -h "${node%:*}" -p "${node#*:}" --raw SENTINEL "$@" 2>/dev/null); then
+            printf '%s\n' "$answer"
+            return 0
+        fi
+    done
+    return 1
+}
+
+# Whether a replica other than this node is up; flags read "slave" alone.
+live_replica() {
+    sentinel replicas "$SENTINEL_MASTER" | awk -v self="$NODE_NAME" '
+        NR % 2 { field = $0; next }
+        field == "ip" { ip = $0 }
+        field == "flags" && ip != self && $0 == "slave" { live = 1 }
+        END { exit !live }'
+}
+
+while :; do
+    primary=$(sentinel get-master-addr-by-name "$SENTINEL_MASTER" || true)
+    host=$(printf '%s\n' "$primary" | sed -n 1p)
+    port=$(printf '%s\n' "$primary" | sed -n 2p)
+    case "$host" in
+        "" | ERR*)
+            # No Sentinel is up yet, so this is a fresh deployment.
+            if [ "$NODE_NAME" != "$INITIAL_PRIMARY" ]; then
+                set -- "$@" --replicaof "$INITIAL_PRIMARY" 6379
+            fi
+            break
+            ;;
+        "$NODE_NAME")
+            if ! live_replica; then
+                # Nothing left to lose: every other node is down too.
+                break
+            fi
+            echo "waiting for the Sentinels to promote a replica" >&2
+            sleep 1
+            ;;
+        *)
+            set -- "$@" --replicaof "$host" "$port"
+            break
+            ;;
+    esac
+done
+
+exec docker-entrypoint.sh redis-server "$@" --replica-announce-ip "$NODE_NAME"
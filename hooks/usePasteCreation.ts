@@ -117,10 +117,12 @@ export const usePasteCreation = (props: UsePasteCreationProps = {}) => {
             };
 
             let keyParam = '';
+            // the server only answers requests carrying a token derived from the link
+            let linkSecret: string;
 
             if (password().trim()) {
                 const salt = CryptoService.generateSalt();
-                const wrapperKey = await CryptoService.deriveKeyFromPassword(password(), salt);
+                const { key: wrapperKey, accessProof } = await CryptoService.derivePasswordSecrets(password(), salt);
                 const contentKeyString = await CryptoService.exportKey(contentKey);
                 const { iv: keyIv, data: encryptedKeyData } = await CryptoService.encryptText(contentKeyString, wrapperKey);
 
@@ -128,11 +130,18 @@ export const usePasteCreation = (props: UsePasteCreationProps = {}) => {
                 payload.salt = await arrayBufferToBase64(salt.buffer as ArrayBuffer);
                 payload.encryptedKey = encryptedKeyData;
                 payload.keyIv = keyIv;
-                keyParam = '';
+                // the server then refuses the ciphertext to anyone without the password
+                payload.accessHash = await CryptoService.hashToken(accessProof);
+                // no key in the link, so it carries a random capability instead
+                linkSecret = CryptoService.generateCapability();
+                keyParam = `&cap=${linkSecret}`;
             } else {
                 const keyString = await CryptoService.exportKeyRaw(contentKey);
+                linkSecret = keyString;
                 keyParam = `&key=${keyString}`;
             }
+            const readToken = await CryptoService.deriveReadToken(linkSecret);
+            payload.readHash = await CryptoService.hashToken(readToken);
 
             // Check payload size (1.5MB limit)
             const payloadSize = new Blob([JSON.stringify(payload)]).size;
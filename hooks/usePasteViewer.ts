@@ -7,13 +7,24 @@ import { isValidLanguage } from '../lib/constants';
 
 export type ViewerStatus = 'loading' | 'error' | 'password_required' | 'decrypting' | 'success';
 
-export const usePasteViewer = (pasteId: () => string, decryptionKey: () => string | null) => {
+export const usePasteViewer = (
+  pasteId: () => string,
+  decryptionKey: () => string | null,
+  capability: () => string | null = () => null
+) => {
   const [status, setStatus] = createSignal<ViewerStatus>('loading');
   const [pasteData, setPasteData] = createSignal<EncryptedPaste | null>(null);
   const [decryptedPaste, setDecryptedPaste] = createSignal<DecryptedPaste | null>(null);
   const [errorMsg, setErrorMsg] = createSignal('');
-
-  const decryptContent = async (data: EncryptedPaste, keyString: string, isPasswordDerived = false) => {
+  const [metadata, setMetadata] = createSignal<StorageService.PasteMetadata | null>(null);
+  const [readToken, setReadToken] = createSignal<string | undefined>(undefined);
+
+  const decryptContent = async (
+    data: EncryptedPaste,
+    keyString: string,
+    isPasswordDerived = false,
+    derivedKey?: CryptoKey
+  ) => {
     setStatus('decrypting');
     try {
       let contentKey: CryptoKey;
@@ -23,7 +34,7 @@ export const usePasteViewer = (pasteId: () => string, decryptionKey: () => strin
           throw new Error("Corrupt password data.");
         }
         const salt = new Uint8Array(base64ToArrayBuffer(data.salt));
-        const wrapperKey = await CryptoService.deriveKeyFromPassword(keyString, salt);
+        const wrapperKey = derivedKey ?? await CryptoService.deriveKeyFromPassword(keyString, salt);
         const decryptedKeyJson = await CryptoService.decryptText(data.encryptedKey, data.keyIv, wrapperKey);
         contentKey = await CryptoService.importKey(decryptedKeyJson);
       } else {
@@ -63,10 +74,10 @@ export const usePasteViewer = (pasteId: () => string, decryptionKey: () => strin
       });
       setStatus('success');
 
-      // If Burn After Read, delete now that we have successfully decrypted
+      // If Burn After Read, acknowledge now that we have successfully decrypted
       // We pass the burnToken to prove we decrypted it
-      if (data.burnAfterRead) {
-        StorageService.deletePaste(data.id, burnToken).catch(console.error);
+      if (data.burnAfterRead && burnToken) {
+        StorageService.ackPaste(data.id, burnToken, readToken()).catch(console.error);
       }
 
     } catch (err: any) {
@@ -83,14 +94,21 @@ export const usePasteViewer = (pasteId: () => string, decryptionKey: () => strin
   createEffect(() => {
     const currentPasteId = pasteId();
     const currentKey = decryptionKey();
+    const currentCapability = capability();
 
     const fetchPaste = async () => {
       try {
+        // links made before read tokens carry neither, and need no token
+        const linkSecret = currentKey ?? currentCapability;
+        const token = linkSecret ? await CryptoService.deriveReadToken(linkSecret) : undefined;
+        setReadToken(token);
+
         // fetch metadata first to check if password is required without downloading full payload
-        const metadata = await StorageService.getPasteMetadata(currentPasteId);
+        const metadata = await StorageService.getPasteMetadata(currentPasteId, token);
         if (!metadata || !metadata.exists) {
           throw new Error("Paste not found, expired, or burned.");
         }
+        setMetadata(metadata);
 
         // if password required, prompt user before fetching full encrypted content
         if (metadata.hasPassword) {
@@ -103,7 +121,7 @@ export const usePasteViewer = (pasteId: () => string, decryptionKey: () => strin
           throw new Error("Decryption key missing.");
         }
 
-        const encryptedData = await StorageService.getPaste(currentPasteId);
+        const encryptedData = await StorageService.getPaste(currentPasteId, undefined, token);
         if (!encryptedData) {
           throw new Error("Paste not found, expired, or burned.");
         }
@@ -114,7 +132,11 @@ export const usePasteViewer = (pasteId: () => string, decryptionKey: () => strin
       } catch (err: any) {
         console.error(err);
         setStatus('error');
-        setErrorMsg(err.message || "Failed to load paste.");
+        if (err instanceof StorageService.ApiError && err.code === 'paste_reserved') {
+          setErrorMsg("Someone else is opening this paste right now. Try again in a minute.");
+        } else {
+          setErrorMsg(err.message || "Failed to load paste.");
+        }
       }
     };
 
@@ -127,20 +149,37 @@ export const usePasteViewer = (pasteId: () => string, decryptionKey: () => strin
 
     try {
       let encryptedData = pasteData();
+      let derivedKey: CryptoKey | undefined;
 
       if (!encryptedData) {
+        // the server only hands out the ciphertext for a proof derived from the password
+        let accessProof: string | undefined;
+        const meta = metadata();
+        if (meta?.accessProof && meta.salt) {
+          const salt = new Uint8Array(base64ToArrayBuffer(meta.salt));
+          ({ key: derivedKey, accessProof } = await CryptoService.derivePasswordSecrets(password, salt));
+        }
+
         // fetch full paste only after password is provided
-        encryptedData = await StorageService.getPaste(pasteId());
+        encryptedData = await StorageService.getPaste(pasteId(), accessProof, readToken());
         if (!encryptedData) {
           throw new Error("Paste not found, expired, or burned.");
         }
         setPasteData(encryptedData);
       }
 
-      await decryptContent(encryptedData, password, true);
+      await decryptContent(encryptedData, password, true, derivedKey);
     } catch (err: any) {
       setStatus('password_required');
-      setErrorMsg(err.message || "Failed to load paste.");
+      if (err instanceof StorageService.ApiError && err.code === 'invalid_access_proof') {
+        setErrorMsg("Incorrect password.");
+      } else if (err instanceof StorageService.ApiError && err.code === 'access_locked') {
+        setErrorMsg("Too many wrong passwords. Try again later.");
+      } else if (err instanceof StorageService.ApiError && err.code === 'paste_reserved') {
+        setErrorMsg("Someone else is opening this paste right now. Try again in a minute.");
+      } else {
+        setErrorMsg(err.message || "Failed to load paste.");
+      }
     }
   };
 
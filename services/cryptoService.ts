@@ -58,10 +58,16 @@ export const generateSalt = (): Uint8Array => {
 };
 
 /**
- * Derive a key from a password using Argon2id
- * Security: Argon2id, 1 iteration, 64MB memory, 1 parallelism, 32 byte hash
+ * Derive the key-wrapping key and the server access proof from a password
+ * Security: Argon2id, 8 iterations, 64MB memory, 1 parallelism, 32 byte hash.
+ * The proof is SHA-256 over a label and the Argon2id output, so it reveals
+ * nothing about the key, and guessing the password from its stored hash
+ * costs a full Argon2id run per guess.
  */
-export const deriveKeyFromPassword = async (password: string, salt: Uint8Array): Promise<CryptoKey> => {
+export const derivePasswordSecrets = async (
+  password: string,
+  salt: Uint8Array
+): Promise<{ key: CryptoKey; accessProof: string }> => {
   const derivedKeyHex = await argon2id({
     password,
     salt,
@@ -74,13 +80,41 @@ export const deriveKeyFromPassword = async (password: string, salt: Uint8Array):
 
   const keyBuffer = hexToUint8Array(derivedKeyHex);
 
-  return window.crypto.subtle.importKey(
+  const key = await window.crypto.subtle.importKey(
     "raw",
     keyBuffer as BufferSource,
     { name: "AES-GCM" },
     true,
     ["encrypt", "decrypt"]
   );
+  const accessProof = await hashToken(`ghostbin-access-proof:${derivedKeyHex}`);
+
+  return { key, accessProof };
+};
+
+/**
+ * Generate the link secret for pastes whose link carries no key
+ * Security: 32 bytes (256 bits), hex encoded so it survives the URL fragment
+ */
+export const generateCapability = (): string => {
+  const bytes = window.crypto.getRandomValues(new Uint8Array(32));
+  return Array.from(bytes).map(b => b.toString(16).padStart(2, '0')).join('');
+};
+
+/**
+ * Derive the read token from the secret in the link fragment
+ * The server keeps only its hash and answers anyone without the token as if
+ * the paste did not exist, so the ID alone reveals nothing.
+ */
+export const deriveReadToken = async (linkSecret: string): Promise<string> => {
+  return hashToken(`ghostbin-read-token:${linkSecret}`);
+};
+
+/**
+ * Derive a key from a password using Argon2id
+ */
+export const deriveKeyFromPassword = async (password: string, salt: Uint8Array): Promise<CryptoKey> => {
+  return (await derivePasswordSecrets(password, salt)).key;
 };
 
 /**
@@ -2,12 +2,38 @@ import { EncryptedPaste, CreatePastePayload } from '../types';
 
 const API_BASE = '/api/v1';
 
+/** Error carrying the stable `code` from the server's problem+json body. */
+export class ApiError extends Error {
+  constructor(
+    public readonly status: number,
+    public readonly code: string | null,
+    public readonly retryAfterMs: number | null,
+  ) {
+    super(`Server error: ${status}${code ? ` (${code})` : ''}`);
+  }
+}
+
+const toApiError = async (response: Response): Promise<ApiError> => {
+  let code: string | null = null;
+  try {
+    const problem = await response.json();
+    if (typeof problem?.code === 'string') code = problem.code;
+  } catch {
+    // Non-problem body (e.g. from a proxy); fall back to the status alone.
+  }
+  const retryAfter = Number(response.headers.get('Retry-After'));
+  const retryAfterMs = Number.isFinite(retryAfter) && retryAfter > 0 ? retryAfter * 1000 : null;
+  return new ApiError(response.status, code, retryAfterMs);
+};
+
 export interface PasteMetadata {
   exists: boolean;
   hasPassword: boolean;
   burnAfterRead: boolean;
   createdAt: number;
   expiresAt: number | null;
+  accessProof?: boolean; // reads need X-Access-Proof, derived with `salt`
+  salt?: string | null;
 }
 
 export const savePaste = async (paste: CreatePastePayload, headers?: Record<string, string>): Promise<string> => {
@@ -21,22 +47,34 @@ export const savePaste = async (paste: CreatePastePayload, headers?: Record<stri
   });
 
   if (!response.ok) {
-    throw new Error(`Server error: ${response.status}`);
+    throw await toApiError(response);
   }
 
   const data = await response.json();
   return data.id;
 };
 
+// Pastes created with a readHash look missing to requests without the token.
+const readTokenHeaders = (readToken?: string): Record<string, string> =>
+  readToken ? { 'X-Read-Token': readToken } : {};
+
 const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
 
-export const getPaste = async (id: string): Promise<EncryptedPaste | null> => {
+export const getPaste = async (
+  id: string,
+  accessProof?: string,
+  readToken?: string
+): Promise<EncryptedPaste | null> => {
   let attempt = 0;
   const maxRetries = 3;
   let delay = 300;
+  const headers: Record<string, string> = readTokenHeaders(readToken);
+  if (accessProof) {
+    headers['X-Access-Proof'] = accessProof;
+  }
 
   while (true) {
-    const response = await fetch(`${API_BASE}/paste/${id}`);
+    const response = await fetch(`${API_BASE}/paste/${id}`, { headers });
 
     if (response.status === 404) return null;
 
@@ -44,21 +82,25 @@ export const getPaste = async (id: string): Promise<EncryptedPaste | null> => {
       return response.json();
     }
 
-    if (response.status === 429 || response.status >= 500) {
+    const error = await toApiError(response);
+
+    // Only transient statuses; a 500 will not fix itself by hammering it,
+    // and a paste locked after wrong passwords stays locked for a while.
+    if ((response.status === 429 && error.code !== 'access_locked') || response.status === 503) {
       if (attempt < maxRetries) {
         attempt++;
-        await sleep(delay);
+        await sleep(error.retryAfterMs ?? delay);
         delay *= 2;
         continue;
       }
     }
 
-    throw new Error(`Server error: ${response.status}`);
+    throw error;
   }
 };
 
-export const deletePaste = async (id: string, burnToken?: string): Promise<void> => {
-  const headers: HeadersInit = {};
+export const deletePaste = async (id: string, burnToken?: string, readToken?: string): Promise<void> => {
+  const headers: Record<string, string> = readTokenHeaders(readToken);
   if (burnToken) {
     headers['X-Burn-Token'] = burnToken;
   }
@@ -73,8 +115,23 @@ export const deletePaste = async (id: string, burnToken?: string): Promise<void>
   }
 };
 
-export const getPasteMetadata = async (id: string): Promise<PasteMetadata | null> => {
-  const response = await fetch(`${API_BASE}/paste/${id}/metadata`);
+// Confirms a burn-after-read paste was decrypted, so the server destroys it.
+// Until then it only holds the paste for us, and lets it go if we never call.
+export const ackPaste = async (id: string, burnToken: string, readToken?: string): Promise<void> => {
+  const response = await fetch(`${API_BASE}/paste/${id}/ack`, {
+    method: 'POST',
+    headers: { ...readTokenHeaders(readToken), 'X-Burn-Token': burnToken },
+  });
+
+  if (!response.ok) {
+    console.error(`Failed to acknowledge paste ${id}: ${response.status}`);
+  }
+};
+
+export const getPasteMetadata = async (id: string, readToken?: string): Promise<PasteMetadata | null> => {
+  const response = await fetch(`${API_BASE}/paste/${id}/metadata`, {
+    headers: readTokenHeaders(readToken),
+  });
 
   if (!response.ok) {
     return null;
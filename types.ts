@@ -13,6 +13,8 @@ export interface EncryptedPaste {
   encryptedKey?: string; // Base64 encoded ciphertext of the content key
   keyIv?: string; // Base64 encoded IV for the key encryption
   burnTokenHash?: string; // SHA-256 hash of the burn token
+  accessHash?: string; // SHA-256 hash of the access proof (create only, never returned)
+  readHash?: string; // SHA-256 hash of the read token (create only, never returned)
 }
 
 export type CreatePastePayload = Omit<EncryptedPaste, 'id'>;